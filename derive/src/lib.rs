@@ -0,0 +1,393 @@
+//! This crate provides `#[derive(Compile)]`, which implements `jit::Compile`
+//! for your own types so they can be passed into and out of LibJIT functions.
+//!
+//! Structs must be `#[repr(C)]`, so the layout LibJIT is given matches the
+//! layout Rust uses, including any padding between fields. Packed structs
+//! aren't supported, since their fields can't be loaded where they are.
+//!
+//! Enums without fields must be given an integer representation, like
+//! `#[repr(u8)]` or `#[repr(C, u8)]`, and are compiled as that integer, so any
+//! explicit discriminants like `A = 5` are kept. Enums with fields must be
+//! `#[repr(C, u8)]` or similar, and are compiled as a struct of a `tag` holding
+//! the index of the variant and a `payload` union of the variants, which
+//! `Val::discriminant`, `Val::is_variant` and `Val::variant_field` can read.
+//!
+//! Unions must be `#[repr(C)]`, and are compiled as a copy of their bytes,
+//! since there's no telling which field is in use.
+//!
+//! This also provides `#[derive(FromJit)]`, which implements `jit::FromJit`
+//! for structs and unions so they can be read back from values laid out by
+//! LibJIT. Structs are read field by field, checking the LibJIT type has
+//! fields of the right types, and unions are read as a copy of their bytes.
+//!
+//! ```rust,ignore
+//! extern crate jit;
+//! #[macro_use]
+//! extern crate jit_derive;
+//! use jit::*;
+//!
+//! #[derive(Compile)]
+//! #[repr(C)]
+//! struct Position {
+//!     x: f64,
+//!     y: f64
+//! }
+//! ```
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate syn;
+#[macro_use]
+extern crate quote;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span, TokenStream as Tokens};
+use syn::{Attribute, Data, DataEnum, DataStruct, DataUnion, DeriveInput, Fields, Generics, Index, Member, Meta, NestedMeta};
+
+static BAD_STRUCT: &'static str = "jit-compatible structs must have a fixed layout, mark with #[repr(C)] to fix";
+static PACKED_STRUCT: &'static str = "jit-compatible structs can't be packed, remove #[repr(packed)] to fix";
+static BAD_UNION: &'static str = "jit-compatible unions must have a fixed layout, mark with #[repr(C)] to fix";
+static BAD_ENUM: &'static str = "jit-compatible enums must have a fixed layout, mark with #[repr(u8)] or #[repr(C, u8)] to fix";
+static BAD_DISCRIMINANT: &'static str = "jit-compatible enums with fields can't have explicit discriminants";
+static BAD_FROM_JIT: &'static str = "only structs and unions can be read back from LibJIT";
+static INT_REPRS: &'static [&'static str] = &["i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "isize", "usize"];
+
+#[proc_macro_derive(Compile)]
+pub fn derive_compile(input: TokenStream) -> TokenStream {
+    let ast: DeriveInput = syn::parse(input).unwrap();
+    let expanded = match ast.data {
+        Data::Struct(ref data) => expand_struct(&ast, data),
+        Data::Enum(ref data) => expand_enum(&ast, data),
+        Data::Union(ref data) => expand_union(&ast, data)
+    };
+    match expanded {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+#[proc_macro_derive(FromJit)]
+pub fn derive_from_jit(input: TokenStream) -> TokenStream {
+    let ast: DeriveInput = syn::parse(input).unwrap();
+    let expanded = match ast.data {
+        Data::Struct(ref data) => Ok(expand_from_jit_struct(&ast, data)),
+        Data::Union(_) => Ok(expand_from_jit_union(&ast)),
+        Data::Enum(_) => Err(syn::Error::new_spanned(&ast.ident, BAD_FROM_JIT))
+    };
+    match expanded {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+/// Get the representations given in `#[repr(..)]` attributes
+fn get_reprs(attrs: &[Attribute]) -> Vec<String> {
+    let mut reprs = Vec::new();
+    for attr in attrs {
+        if let Ok(Meta::List(list)) = attr.parse_meta() {
+            if list.ident == "repr" {
+                for item in list.nested {
+                    match item {
+                        NestedMeta::Meta(Meta::Word(word)) => reprs.push(word.to_string()),
+                        NestedMeta::Meta(Meta::List(list)) => reprs.push(list.ident.to_string()),
+                        _ => ()
+                    }
+                }
+            }
+        }
+    }
+    reprs
+}
+
+/// Get the `impl` line for `Compile`, bounding every type parameter by `Compile`
+fn impl_header(ast: &DeriveInput) -> Tokens {
+    impl_trait(ast, quote!(::jit::Compile<'jit>), true)
+}
+
+/// Get the `impl` line for `trait_path`, bounding every type parameter by it
+fn impl_trait(ast: &DeriveInput, trait_path: Tokens, with_lifetime: bool) -> Tokens {
+    let name = &ast.ident;
+    let mut generics: Generics = ast.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#trait_path));
+    }
+    if with_lifetime {
+        generics.params.insert(0, parse_quote!('jit));
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = ast.generics.split_for_impl();
+    quote! {
+        impl #impl_generics #trait_path for #name #ty_generics #where_clause
+    }
+}
+
+/// Get the members that access each field
+fn members(fields: &Fields) -> Vec<Member> {
+    fields.iter().enumerate().map(|(index, field)| match field.ident {
+        Some(ref ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index::from(index))
+    }).collect()
+}
+
+/// Get an expression making a struct type out of `fields`, named by their
+/// names or, for tuples, by their indices
+fn new_struct(fields: &Fields) -> Tokens {
+    new_type(quote!(new_struct), fields)
+}
+
+/// Get an expression making a struct or union type out of `fields` with the
+/// `Type` constructor given
+fn new_type(constructor: Tokens, fields: &Fields) -> Tokens {
+    let types = fields.iter().map(|field| &field.ty);
+    let names = if fields.iter().count() == 0 {
+        quote!()
+    } else {
+        let names = fields.iter().enumerate().map(|(index, field)| match field.ident {
+            Some(ref ident) => ident.to_string(),
+            None => index.to_string()
+        });
+        quote!(let mut ty = ty; ty.set_names(&[#(#names),*]);)
+    };
+    quote!({
+        let fields: Vec<::jit::CowType<'jit>> = vec![#(::jit::get::<#types>()),*];
+        let fields: Vec<&::jit::Ty> = fields.iter().map(|field| &**field).collect();
+        let ty = ::jit::Type::#constructor(&fields);
+        #names
+        ty
+    })
+}
+
+fn expand_struct(ast: &DeriveInput, data: &DataStruct) -> syn::Result<Tokens> {
+    let reprs = get_reprs(&ast.attrs);
+    if reprs.iter().any(|repr| repr == "packed") {
+        return Err(syn::Error::new_spanned(&ast.ident, PACKED_STRUCT))
+    }
+    if !reprs.iter().any(|repr| repr == "C") {
+        return Err(syn::Error::new_spanned(&ast.ident, BAD_STRUCT))
+    }
+    let header = impl_header(ast);
+    let new_struct = new_struct(&data.fields);
+    let members = &members(&data.fields);
+    let types = &data.fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let types2 = types;
+    let indices = &(0..members.len()).collect::<Vec<_>>();
+    let indices2 = indices;
+    // `#[repr(C)]` puts each field at the next offset aligned for its type, so
+    // the offsets can be worked out without making a value to measure
+    Ok(quote! {
+        #header {
+            fn compile(self, func: &'jit ::jit::UncompiledFunction) -> &'jit ::jit::Val {
+                let ty = <Self as ::jit::Compile<'jit>>::get_type();
+                let value = ::jit::Val::new(func, &ty);
+                value.set_addressable();
+                let address = func.insn_address_of(value);
+                #(
+                    let offset = ty.fields().nth(#indices).unwrap().get_offset();
+                    func.insn_store_relative(address, offset, ::jit::Compile::compile(self.#members, func));
+                )*
+                value
+            }
+            #[allow(unused_mut, unused_assignments, unused_variables)]
+            fn get_type() -> ::jit::CowType<'jit> {
+                use std::mem;
+                let mut ty = #new_struct;
+                let mut offset = 0;
+                #(
+                    let align = mem::align_of::<#types>();
+                    offset = (offset + align - 1) / align * align;
+                    ty.set_offset(#indices2, offset);
+                    offset += mem::size_of::<#types2>();
+                )*
+                ty.set_size_and_alignment(mem::size_of::<Self>(), mem::align_of::<Self>());
+                ty.into()
+            }
+        }
+    })
+}
+
+fn expand_enum(ast: &DeriveInput, data: &DataEnum) -> syn::Result<Tokens> {
+    let reprs = get_reprs(&ast.attrs);
+    let int = match reprs.iter().find(|repr| INT_REPRS.iter().any(|int| *int == repr.as_str())) {
+        Some(int) => Ident::new(int, Span::call_site()),
+        None => return Err(syn::Error::new_spanned(&ast.ident, BAD_ENUM))
+    };
+    let has_fields = data.variants.iter().any(|variant| variant.fields.iter().count() > 0);
+    let is_c = reprs.iter().any(|repr| repr == "C");
+    let header = impl_header(ast);
+    if !has_fields {
+        // casting keeps any explicit discriminants, like `A = 5`
+        return Ok(quote! {
+            #header {
+                fn compile(self, func: &'jit ::jit::UncompiledFunction) -> &'jit ::jit::Val {
+                    ::jit::Compile::compile(self as #int, func)
+                }
+                fn get_type() -> ::jit::CowType<'jit> {
+                    ::jit::get::<#int>()
+                }
+            }
+        })
+    }
+    if !is_c {
+        return Err(syn::Error::new_spanned(&ast.ident, BAD_ENUM))
+    }
+    if let Some(variant) = data.variants.iter().find(|variant| variant.discriminant.is_some()) {
+        return Err(syn::Error::new_spanned(&variant.ident, BAD_DISCRIMINANT))
+    }
+    let name = &ast.ident;
+    let variant_types = data.variants.iter().map(|variant| new_struct(&variant.fields));
+    let variant_names = data.variants.iter().map(|variant| variant.ident.to_string());
+    let arms = data.variants.iter().enumerate().map(|(index, variant)| {
+        let ident = &variant.ident;
+        let bindings = &(0..variant.fields.iter().count())
+            .map(|field| Ident::new(&format!("field{}", field), Span::call_site()))
+            .collect::<Vec<_>>();
+        let bindings2 = bindings;
+        let pattern = match variant.fields {
+            Fields::Named(_) => {
+                let names = variant.fields.iter().map(|field| field.ident.as_ref().unwrap());
+                quote!(#name::#ident { #(#names: #bindings),* })
+            },
+            Fields::Unnamed(_) => quote!(#name::#ident(#(#bindings),*)),
+            Fields::Unit => quote!(#name::#ident)
+        };
+        let store = if bindings.is_empty() {
+            quote!()
+        } else {
+            let indices = 0..bindings.len();
+            quote! {
+                let variant = payload.get_type().fields().nth(#index).unwrap().get_type();
+                #(
+                    let offset = payload.get_offset() + variant.fields().nth(#indices).unwrap().get_offset();
+                    func.insn_store_relative(address, offset, ::jit::Compile::compile(#bindings2, func));
+                )*
+            }
+        };
+        quote!(#pattern => { #store #index })
+    });
+    // `#[repr(C, u8)]` lays the enum out like a C struct of the tag and a C
+    // union of C structs, which is how LibJIT lays these types out anyway
+    Ok(quote! {
+        #header {
+            #[allow(unused_variables)]
+            fn compile(self, func: &'jit ::jit::UncompiledFunction) -> &'jit ::jit::Val {
+                let ty = <Self as ::jit::Compile<'jit>>::get_type();
+                let value = ::jit::Val::new(func, &ty);
+                value.set_addressable();
+                let address = func.insn_address_of(value);
+                let payload = ty.fields().nth(1).unwrap();
+                let index = match self {
+                    #(#arms),*
+                };
+                let tag = ty.fields().nth(0).unwrap();
+                func.insn_store_relative(address, tag.get_offset(), ::jit::Compile::compile(index as #int, func));
+                value
+            }
+            fn get_type() -> ::jit::CowType<'jit> {
+                use std::mem;
+                let variants: Vec<::jit::Type> = vec![#(#variant_types),*];
+                let variants: Vec<&::jit::Ty> = variants.iter().map(|variant| &**variant).collect();
+                let mut payload = ::jit::Type::new_union(&variants);
+                payload.set_names(&[#(#variant_names),*]);
+                let tag = ::jit::get::<#int>();
+                let mut ty = ::jit::Type::new_struct(&[&*tag, &*payload]);
+                ty.set_names(&["tag", "payload"]);
+                ty.set_size_and_alignment(mem::size_of::<Self>(), mem::align_of::<Self>());
+                ty.into()
+            }
+        }
+    })
+}
+
+fn expand_union(ast: &DeriveInput, data: &DataUnion) -> syn::Result<Tokens> {
+    let reprs = get_reprs(&ast.attrs);
+    if !reprs.iter().any(|repr| repr == "C") {
+        return Err(syn::Error::new_spanned(&ast.ident, BAD_UNION))
+    }
+    let header = impl_header(ast);
+    let new_union = new_type(quote!(new_union), &Fields::Named(data.fields.clone()));
+    Ok(quote! {
+        #header {
+            fn compile(self, func: &'jit ::jit::UncompiledFunction) -> &'jit ::jit::Val {
+                use std::{mem, ptr};
+                let ty = <Self as ::jit::Compile<'jit>>::get_type();
+                let value = ::jit::Val::new(func, &ty);
+                value.set_addressable();
+                let address = func.insn_address_of(value);
+                // copy the whole union into words, then store them as wide as
+                // they go, so only the tail is stored in smaller pieces
+                let size = mem::size_of::<Self>();
+                let mut words = vec![0u64; (size + 7) / 8];
+                unsafe {
+                    ptr::copy_nonoverlapping(&self as *const Self as *const u8, words.as_mut_ptr() as *mut u8, size);
+                }
+                let bytes = words.as_ptr() as *const u8;
+                let mut offset = 0;
+                for &width in &[8, 4, 2, 1] {
+                    while size - offset >= width {
+                        let word = unsafe {
+                            let ptr = bytes.offset(offset as isize);
+                            match width {
+                                8 => ::jit::Compile::compile(ptr::read_unaligned(ptr as *const u64), func),
+                                4 => ::jit::Compile::compile(ptr::read_unaligned(ptr as *const u32), func),
+                                2 => ::jit::Compile::compile(ptr::read_unaligned(ptr as *const u16), func),
+                                _ => ::jit::Compile::compile(*ptr, func)
+                            }
+                        };
+                        func.insn_store_relative(address, offset, word);
+                        offset += width;
+                    }
+                }
+                value
+            }
+            fn get_type() -> ::jit::CowType<'jit> {
+                use std::mem;
+                let mut ty = #new_union;
+                ty.set_size_and_alignment(mem::size_of::<Self>(), mem::align_of::<Self>());
+                ty.into()
+            }
+        }
+    })
+}
+
+fn expand_from_jit_struct(ast: &DeriveInput, data: &DataStruct) -> Tokens {
+    let header = impl_trait(ast, quote!(::jit::FromJit), false);
+    let count = data.fields.iter().count();
+    let types = &data.fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let types2 = types;
+    let members = members(&data.fields);
+    quote! {
+        #header {
+            #[allow(unused_mut)]
+            fn is_type(ty: &::jit::Ty) -> bool {
+                let mut fields = ty.fields();
+                ty.is_struct() && fields.size_hint().0 == #count
+                    #(&& <#types as ::jit::FromJit>::is_type(fields.next().unwrap().get_type()))*
+            }
+            #[allow(unused_mut, unused_variables)]
+            unsafe fn read_unchecked(ty: &::jit::Ty, ptr: *const ::std::os::raw::c_void) -> Self {
+                let mut fields = ty.fields();
+                Self {
+                    #(#members: {
+                        let field = fields.next().unwrap();
+                        let ptr = (ptr as *const u8).offset(field.get_offset() as isize);
+                        <#types2 as ::jit::FromJit>::read_unchecked(field.get_type(), ptr as *const ::std::os::raw::c_void)
+                    }),*
+                }
+            }
+        }
+    }
+}
+
+fn expand_from_jit_union(ast: &DeriveInput) -> Tokens {
+    let header = impl_trait(ast, quote!(::jit::FromJit), false);
+    quote! {
+        #header {
+            fn is_type(ty: &::jit::Ty) -> bool {
+                ty.is_union() && ty.get_size() == ::std::mem::size_of::<Self>()
+            }
+            unsafe fn read_unchecked(_: &::jit::Ty, ptr: *const ::std::os::raw::c_void) -> Self {
+                ::std::ptr::read_unaligned(ptr as *const Self)
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use raw::*;
+use context::{self, Context};
+use compile::Compile;
+use types::{Ty, Type};
+use util::{self, oom};
+use std::borrow::ToOwned;
+use std::os::raw::c_void;
+use std::marker::PhantomData;
+use std::{mem, ptr};
+/// The context metadata key holding the Rust closures behind its native closures
+const CLOSURES: i32 = -4;
+/// A Rust closure called by a native closure, and the signature it was made with
+struct Entry {
+    func: Box<Fn(&mut ClosureArgs, &mut ClosureReturn)>,
+    signature: Type
+}
+/// A native function pointer that calls a Rust closure, for a signature which
+/// is only known at runtime
+///
+/// The closure is given a `ClosureArgs` to read its arguments from and a
+/// `ClosureReturn` to write its return value to, which check the types asked
+/// for against the signature. The function pointer lasts as long as the context
+/// it was made in, so it can be handed to C libraries or `insn_call_native`.
+///
+/// ```rust
+/// use jit::*;
+/// let ctx = Context::<()>::new();
+/// let sig = get::<fn(i32, i32) -> i32>();
+/// let closure = NativeClosure::new(&ctx, &sig, |args, ret| {
+///     let (a, b) = (args.next::<i32>(), args.next::<i32>());
+///     ret.set(a - b)
+/// });
+/// let func: extern fn(i32, i32) -> i32 = unsafe { closure.as_fn() };
+/// assert_eq!(func(7, 3), 4);
+/// ```
+#[derive(Clone, Copy)]
+pub struct NativeClosure<'a> {
+    ptr: *mut c_void,
+    marker: PhantomData<&'a ()>
+}
+impl<'a> NativeClosure<'a> {
+    /// Make a native function pointer with the signature given that calls `func`
+    ///
+    /// This panics if the JIT doesn't support closures on this platform.
+    pub fn new<T, F>(ctx: &'a Context<T>, signature: &Ty, func: F) -> NativeClosure<'a>
+        where F:Fn(&mut ClosureArgs, &mut ClosureReturn) + 'static {
+        if !::supports_closures() {
+            panic!("Closures are not supported on this platform")
+        }
+        unsafe {
+            let entry = Box::new(Entry {
+                func: Box::new(func),
+                signature: signature.to_owned()
+            });
+            let data = &*entry as *const Entry as *mut c_void;
+            let signature: jit_type_t = (&*entry.signature).into();
+            closures(ctx.into()).push(entry);
+            context::use_memory(ctx.into());
+            let ptr = jit_closure_create(ctx.into(), signature, Some(call_closure), data);
+            if ptr.is_null() {
+                oom()
+            }
+            NativeClosure {
+                ptr: ptr,
+                marker: PhantomData
+            }
+        }
+    }
+    /// Get the native function pointer
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.ptr
+    }
+    /// Convert the function pointer to a function type, which must match the
+    /// signature the closure was made with
+    pub unsafe fn as_fn<F>(&self) -> F where F:Copy {
+        assert_eq!(mem::size_of::<F>(), mem::size_of::<*mut c_void>());
+        mem::transmute_copy(&self.ptr)
+    }
+}
+/// Reads the arguments a native closure was called with
+pub struct ClosureArgs<'a> {
+    signature: &'a Ty,
+    args: *mut *mut c_void,
+    length: usize,
+    index: usize
+}
+impl<'a> ClosureArgs<'a> {
+    /// Get the number of arguments
+    pub fn len(&self) -> usize {
+        self.length
+    }
+    /// Get the type of the argument at `index`
+    pub fn get_type(&self, index: usize) -> Option<&'a Ty> {
+        self.signature.params().nth(index)
+    }
+    /// Get a pointer to the argument at `index`, whatever its type
+    ///
+    /// This panics if there is no argument at `index`.
+    pub fn get_ptr(&self, index: usize) -> *mut c_void {
+        if index >= self.length {
+            panic!("Closure with signature {:?} has no argument #{}", self.signature, index)
+        }
+        unsafe {
+            *self.args.offset(index as isize)
+        }
+    }
+    /// Read the argument at `index` as the type given
+    ///
+    /// This panics if there is no argument at `index` or it has a different type.
+    pub fn get<T>(&self, index: usize) -> T where T:Compile<'a> + Copy {
+        let ptr = self.get_ptr(index);
+        let param = self.get_type(index).unwrap();
+        let ty = ::get::<T>();
+        if param != &*ty {
+            panic!("Argument #{} to closure with signature {:?} should be {:?}, but got {:?}", index, self.signature, param, &*ty)
+        }
+        unsafe {
+            ptr::read(ptr as *const T)
+        }
+    }
+    /// Read the next argument as the type given
+    ///
+    /// This panics if there are no arguments left or the next one has a different type.
+    pub fn next<T>(&mut self) -> T where T:Compile<'a> + Copy {
+        let value = self.get(self.index);
+        self.index += 1;
+        value
+    }
+}
+/// Writes the value a native closure returns
+pub struct ClosureReturn<'a> {
+    signature: &'a Ty,
+    result: *mut c_void
+}
+impl<'a> ClosureReturn<'a> {
+    /// Get a pointer to the buffer the return value is written to, whatever its type
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.result
+    }
+    /// Set the return value
+    ///
+    /// This panics if the value has a different type to the return type.
+    pub fn set<T>(&mut self, value: T) where T:Compile<'a> {
+        let ret = self.signature.get_return().unwrap();
+        let ty = ::get::<T>();
+        if ret != &*ty {
+            panic!("Closure with signature {:?} should return {:?}, but got {:?}", self.signature, ret, &*ty)
+        }
+        unsafe {
+            ptr::write(self.result as *mut T, value)
+        }
+    }
+}
+/// Get the Rust closures behind the native closures of the context given
+fn closures<'a>(context: jit_context_t) -> &'a mut Vec<Box<Entry>> {
+    unsafe {
+        let mut closures = jit_context_get_meta(context, CLOSURES);
+        if closures.is_null() {
+            let boxed: Box<Vec<Box<Entry>>> = Box::new(Vec::new());
+            if jit_context_set_meta(context, CLOSURES, mem::transmute(boxed), Some(::free_data::<Vec<Box<Entry>>>)) == 0 {
+                oom()
+            }
+            closures = jit_context_get_meta(context, CLOSURES);
+        }
+        mem::transmute(closures)
+    }
+}
+/// Call the Rust closure behind a native closure
+///
+/// This aborts if the closure panics, like when it asks for the wrong arguments.
+extern fn call_closure(signature: jit_type_t, result: *mut c_void, args: *mut *mut c_void, data: *mut c_void) {
+    util::abort_on_panic(|| unsafe {
+        let entry = &*(data as *const Entry);
+        let signature: &Ty = signature.into();
+        if let Some(ret) = signature.get_return() {
+            ptr::write_bytes(result as *mut u8, 0, ret.get_size());
+        }
+        let mut args = ClosureArgs {
+            signature: signature,
+            args: args,
+            length: signature.params().count(),
+            index: 0
+        };
+        let mut ret = ClosureReturn {
+            signature: signature,
+            result: result
+        };
+        (entry.func)(&mut args, &mut ret)
+    })
+}
@@ -149,6 +149,46 @@ impl<'a> Compile<'a> for &'a str {
         ty.into()
     }
 }
+impl<'a, T> Compile<'a> for &'a [T] where T:Compile<'a> {
+    #[inline(always)]
+    fn compile(self, func:&'a UncompiledFunction) -> &'a Val {
+        let ty = Self::get_type();
+        let slice = Val::new(func, &ty);
+        slice.set_addressable();
+        let addr = func.insn_address_of(slice);
+        let mut fields = ty.fields();
+        func.insn_store_relative(addr, fields.next().unwrap().get_offset(), func.insn_of(self.as_ptr()));
+        func.insn_store_relative(addr, fields.next().unwrap().get_offset(), func.insn_of(self.len()));
+        slice
+    }
+    #[inline(always)]
+    fn get_type() -> CowType<'a> {
+        let mut ty = Type::new_struct(&[&get::<*const T>(), &get::<usize>()]);
+        ty.set_names(&["ptr", "len"]);
+        ty.set_size_and_alignment(mem::size_of::<*const [T]>(), mem::align_of::<*const [T]>());
+        ty.into()
+    }
+}
+impl<'a, T> Compile<'a> for &'a mut [T] where T:Compile<'a> {
+    #[inline(always)]
+    fn compile(self, func:&'a UncompiledFunction) -> &'a Val {
+        let ty = Self::get_type();
+        let slice = Val::new(func, &ty);
+        slice.set_addressable();
+        let addr = func.insn_address_of(slice);
+        let mut fields = ty.fields();
+        func.insn_store_relative(addr, fields.next().unwrap().get_offset(), func.insn_of(self.as_mut_ptr()));
+        func.insn_store_relative(addr, fields.next().unwrap().get_offset(), func.insn_of(self.len()));
+        slice
+    }
+    #[inline(always)]
+    fn get_type() -> CowType<'a> {
+        let mut ty = Type::new_struct(&[&get::<*mut T>(), &get::<usize>()]);
+        ty.set_names(&["ptr", "len"]);
+        ty.set_size_and_alignment(mem::size_of::<*mut [T]>(), mem::align_of::<*mut [T]>());
+        ty.into()
+    }
+}
 impl<'a, T> Compile<'a> for (T, ) where T: Compile<'a> {
     #[inline(always)]
     fn compile(self, func:&'a UncompiledFunction) -> &'a Val {
@@ -163,8 +203,27 @@ compile_tuple!(A, B => a, b);
 compile_tuple!(A, B, C => a, b, c);
 compile_tuple!(A, B, C, D => a, b, c, d);
 compile_tuple!(A, B, C, D, E => a, b, c, d, e);
-compile_func!(fn() -> R, fn() -> R, extern fn() -> R);
-compile_func!(fn(A) -> R, fn(A) -> R, extern fn(A) -> R);
-compile_func!(fn(A, B) -> R, fn(A, B) -> R, extern fn(A, B) -> R);
-compile_func!(fn(A, B, C) -> R, fn(A, B, C) -> R, extern fn(A, B, C) -> R);
-compile_func!(fn(A, B, C, D) -> R, fn(A, B, C, D) -> R, extern fn(A, B, C, D) -> R);
+compile_tuple!(A, B, C, D, E, F => a, b, c, d, e, f);
+compile_tuple!(A, B, C, D, E, F, G => a, b, c, d, e, f, g);
+compile_tuple!(A, B, C, D, E, F, G, H => a, b, c, d, e, f, g, h);
+compile_tuple!(A, B, C, D, E, F, G, H, I => a, b, c, d, e, f, g, h, i);
+compile_tuple!(A, B, C, D, E, F, G, H, I, J => a, b, c, d, e, f, g, h, i, j);
+compile_tuple!(A, B, C, D, E, F, G, H, I, J, K => a, b, c, d, e, f, g, h, i, j, k);
+compile_tuple!(A, B, C, D, E, F, G, H, I, J, K, L => a, b, c, d, e, f, g, h, i, j, k, l);
+compile_funcs!{
+    (),
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H),
+    (A, B, C, D, E, F, G, H, I),
+    (A, B, C, D, E, F, G, H, I, J),
+    (A, B, C, D, E, F, G, H, I, J, K),
+    (A, B, C, D, E, F, G, H, I, J, K, L)
+}
+compile_arrays!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+                17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32);
@@ -1,11 +1,87 @@
 use raw::*;
+use exception::{self, BuiltinException};
 use function::Func;
-use util::{oom, from_ptr_opt};
+use memory::{self, MemoryManager};
+use util::{oom, from_ptr, from_ptr_opt};
 use std::default::Default;
 use std::marker::PhantomData;
-use std::{mem, ptr};
+use std::os::raw::{c_int, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::{fmt, mem, ptr};
 use std::ops::{Index, IndexMut};
 use cbox::{CBox, DisposeRef};
+/// The context metadata key holding the source locations marked in its functions
+///
+/// Keys below zero are reserved for the state this crate keeps on contexts,
+/// like this and the keys in `debugger`, `memory` and `closure`.
+const LOCATIONS: i32 = -1;
+/// The bytecode offset `insn_mark_location` marks the first location with
+///
+/// Later locations count down from this, so their offsets never clash with the
+/// non-negative ones given to `insn_mark_offset`, nor with `!0`, which LibJIT
+/// gives for code without a marked offset.
+const FIRST_LOCATION: i32 = -2;
+/// The context metadata key holding the driver set by `set_on_demand_driver`
+const ON_DEMAND_DRIVER: i32 = -6;
+/// The context metadata key set once a function or closure has been made in the
+/// context, which makes LibJIT set up its memory with the manager it has then
+const MEMORY_USED: i32 = -5;
+/// The first context metadata key LibJIT reserves for its own options
+const FIRST_OPTION: i32 = 10000;
+/// Panic if `index` is a metadata key reserved by this crate or by LibJIT,
+/// which can't be indexed as tagged data since it holds something else
+fn check_index(index: i32) {
+    if index < 0 || index >= FIRST_OPTION {
+        panic!("Index {} on Context is reserved", index)
+    }
+}
+/// A position in the source code that JIT code was compiled from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// The name of the source file
+    pub file: String,
+    /// The line number in the source file
+    pub line: u32,
+    /// The column number in the line
+    pub column: u32
+}
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+/// Get the source locations marked in the functions of the context given
+pub fn locations<'a>(context: jit_context_t) -> &'a mut Vec<Location> {
+    unsafe {
+        let mut locations = jit_context_get_meta(context, LOCATIONS);
+        if locations.is_null() {
+            let boxed: Box<Vec<Location>> = Box::new(Vec::new());
+            if jit_context_set_meta(context, LOCATIONS, mem::transmute(boxed), Some(::free_data::<Vec<Location>>)) == 0 {
+                oom()
+            }
+            locations = jit_context_get_meta(context, LOCATIONS);
+        }
+        mem::transmute(locations)
+    }
+}
+/// Get the bytecode offset that marks the source location at `index` in the
+/// locations of its context
+pub fn location_offset(index: usize) -> i32 {
+    FIRST_LOCATION - index as i32
+}
+/// Check if the bytecode offset given was marked by `insn_mark_location`
+pub fn is_location_offset(offset: c_uint) -> bool {
+    offset as i32 <= FIRST_LOCATION
+}
+/// Note that a function or closure is being made in the context given, so its
+/// memory manager can't be changed any more
+pub fn use_memory(context: jit_context_t) {
+    unsafe {
+        if jit_context_set_meta_numeric(context, MEMORY_USED, 1) == 0 {
+            oom()
+        }
+    }
+}
 /// Holds all of the functions you have built and compiled. There can be
 /// multiple, but normally there is only one.
 ///
@@ -29,6 +105,9 @@ use cbox::{CBox, DisposeRef};
 /// assert_eq!(ctx[0], 42);
 /// assert_eq!(ctx[1], 21);
 /// ```
+///
+/// Indices below zero, or from 10000 upwards, are reserved for the crate and
+/// for LibJIT, so indexing with them panics.
 pub struct Context<T = ()> {
     _marker: PhantomData<T>
 }
@@ -57,6 +136,7 @@ impl<T> DisposeRef for Context<T> {
 impl<T> Index<i32> for Context<T> {
     type Output = T;
     fn index(&self, index: i32) -> &T {
+        check_index(index);
         unsafe {
             let meta = jit_context_get_meta(self.into(), index);
             if meta.is_null() {
@@ -68,6 +148,7 @@ impl<T> Index<i32> for Context<T> {
 }
 impl<T> IndexMut<i32> for Context<T> where T: Default {
     fn index_mut(&mut self, index: i32) -> &mut T {
+        check_index(index);
         unsafe {
             let meta = jit_context_get_meta(self.into(), index);
             if meta.is_null() {
@@ -86,11 +167,101 @@ impl<T> IndexMut<i32> for Context<T> where T: Default {
 impl<T> Context<T> {
     #[inline(always)]
     /// Create a new JIT Context
+    ///
+    /// This also makes builtin exceptions like division by zero catchable on
+    /// this thread, unless it already has an exception handler.
     pub fn new() -> CBox<Context<T>> {
+        exception::init_handler();
         unsafe {
             CBox::new(jit_context_create())
         }
     }
+    /// Make the context allocate the memory for its generated code and data
+    /// with `manager`, instead of LibJIT's own memory manager
+    ///
+    /// LibJIT sets up the memory of a context when the first function or native
+    /// closure is created in it, and keeps the manager it had then, so this
+    /// panics if one has already been created.
+    pub fn set_memory_manager<M>(&self, manager: M) where M:MemoryManager + 'static {
+        unsafe {
+            if jit_context_get_meta_numeric(self.into(), MEMORY_USED) != 0 {
+                panic!("The memory manager can't be changed after a function or closure is created in the context")
+            }
+            let boxed: Box<Option<Box<MemoryManager>>> = Box::new(Some(Box::new(manager)));
+            if jit_context_set_meta(self.into(), memory::MEMORY_MANAGER, mem::transmute(boxed), Some(::free_data::<Option<Box<MemoryManager>>>)) == 0 {
+                oom()
+            }
+            jit_context_set_memory_manager(self.into(), &memory::MANAGER);
+        }
+    }
+    /// Make `driver` compile the on-demand functions of this context the first
+    /// time they are entered, in place of LibJIT's driver
+    ///
+    /// The driver is given the function being entered and a callback that runs
+    /// its on-demand builder and compiles it, so it can wrap that with its own
+    /// bookkeeping, like logging or timing lazy builds. It runs while the
+    /// context is locked for building, and if it gives an error or panics, the
+    /// call that entered the function throws that exception instead.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// use std::cell::Cell;
+    /// use std::rc::Rc;
+    /// let ctx = Context::<()>::new();
+    /// let built = Rc::new(Cell::new(0));
+    /// let count = built.clone();
+    /// ctx.set_on_demand_driver(move |_, compile| {
+    ///     count.set(count.get() + 1);
+    ///     compile()
+    /// });
+    /// let func = UncompiledFunction::new_on_demand(&ctx, &get::<fn(i32) -> i32>(), |func| {
+    ///     func.insn_return(func.insn_add(&func[0], &func[0]));
+    /// });
+    /// let double = CompiledFunction::to_closure::<i32, i32>(func);
+    /// assert_eq!(double(4), 8);
+    /// assert_eq!(double(5), 10);
+    /// assert_eq!(built.get(), 1);
+    /// ```
+    pub fn set_on_demand_driver<F>(&self, driver: F)
+        where F:Fn(&Func, &Fn() -> Result<(), BuiltinException>) -> Result<(), BuiltinException> + 'static {
+        unsafe {
+            let boxed: Box<Box<OnDemandDriver>> = Box::new(Box::new(driver));
+            if jit_context_set_meta(self.into(), ON_DEMAND_DRIVER, mem::transmute(boxed), Some(::free_data::<Box<OnDemandDriver>>)) == 0 {
+                oom()
+            }
+            jit_context_set_on_demand_driver(self.into(), Some(drive_on_demand));
+        }
+    }
+    /// Get the source location marked with the bytecode offset given by
+    /// `insn_mark_location`
+    ///
+    /// This gives `None` for offsets marked with `insn_mark_offset`.
+    pub fn get_location(&self, offset: u32) -> Option<&Location> {
+        if !is_location_offset(offset) {
+            return None
+        }
+        unsafe {
+            let locations = jit_context_get_meta(self.into(), LOCATIONS) as *const Vec<Location>;
+            if locations.is_null() {
+                None
+            } else {
+                (*locations).get((FIRST_LOCATION - offset as i32) as usize)
+            }
+        }
+    }
+    /// Find the source location of the JIT code at the native program counter
+    /// `pc`, if it is in a function in this context that marked its location
+    pub fn location_at(&self, pc: *mut c_void) -> Option<&Location> {
+        unsafe {
+            let mut handler = ptr::null_mut();
+            let func = jit_function_from_pc(self.into(), pc, &mut handler);
+            if func.is_null() {
+                return None
+            }
+            let offset = _jit_function_get_bytecode(func, handler, pc, 0);
+            self.get_location(offset as c_uint)
+        }
+    }
     /// Iterate through the functions contained inside this context
     pub fn functions(&self) -> Functions {
         Functions {
@@ -109,6 +280,48 @@ impl<T> Drop for Context<T> {
     }
 }
 
+/// A driver set by `Context::set_on_demand_driver`
+type OnDemandDriver = Fn(&Func, &Fn() -> Result<(), BuiltinException>) -> Result<(), BuiltinException>;
+
+/// Compile an on-demand function the first time it is entered with the driver
+/// of its context, and give its entry point
+///
+/// This throws if the driver fails, like LibJIT's own driver does.
+extern fn drive_on_demand(func: jit_function_t) -> *mut c_void {
+    unsafe {
+        let context = jit_function_get_context(func);
+        jit_context_build_start(context);
+        let driver = jit_context_get_meta(context, ON_DEMAND_DRIVER) as *const Box<OnDemandDriver>;
+        let compile = || {
+            let result = match jit_function_get_on_demand_compiler(func) {
+                Some(on_demand) => on_demand(func),
+                None => BuiltinException::CompileError as c_int
+            };
+            if result != 1 {
+                Err(BuiltinException::from_code(result).unwrap_or(BuiltinException::CompileError))
+            } else if jit_function_compile(func) == 0 {
+                Err(BuiltinException::CompileError)
+            } else {
+                Ok(())
+            }
+        };
+        let result = if jit_function_is_compiled(func) != 0 {
+            Ok(())
+        } else if driver.is_null() {
+            compile()
+        } else {
+            let func: &Func = from_ptr(func);
+            panic::catch_unwind(AssertUnwindSafe(|| (*driver)(func, &compile)))
+                .unwrap_or(Err(BuiltinException::CompileError))
+        };
+        jit_context_build_end(context);
+        if let Err(exception) = result {
+            jit_exception_builtin(exception as c_int);
+        }
+        jit_function_to_closure(func)
+    }
+}
+
 pub struct Functions<'a> {
     context: jit_context_t,
     last: jit_function_t,
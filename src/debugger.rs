@@ -0,0 +1,297 @@
+use raw::*;
+use context::Context;
+use function::Func;
+use util::{self, from_ptr, from_ptr_opt, oom};
+use std::os::raw::c_int;
+use std::marker::PhantomData;
+use std::{mem, ptr};
+/// The context metadata key holding the debug hook of the context
+const DEBUG_HOOK: i32 = -2;
+/// Values of `data1` given to `insn_mark_breakpoint` that are understood by the
+/// debugger, where values below `FIRST` are free for the program to use
+pub mod marks {
+    /// The first value reserved for the debugger
+    pub const FIRST: isize = 10000;
+    /// A breakpoint at the start of a line, with the line number as `data2`
+    pub const LINE: isize = 10000;
+    /// A breakpoint at the entry to a function
+    pub const ENTER: isize = 10001;
+    /// A breakpoint at the exit from a function
+    pub const LEAVE: isize = 10002;
+    /// A breakpoint where an exception is thrown
+    pub const THROW: isize = 10003;
+}
+/// The identifier of a thread known to a debugger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadId(jit_debugger_thread_id_t);
+/// The identifier of a breakpoint added to a debugger
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BreakpointId(jit_debugger_breakpoint_id_t);
+/// The kind of thing that caused a debugger event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// The debugger was told to quit
+    Quit,
+    /// A thread stopped at a breakpoint added with `add_breakpoint`
+    HardBreakpoint,
+    /// A thread stopped after stepping
+    SoftBreakpoint,
+    /// A thread was stopped by `interrupt`
+    UserBreakpoint,
+    /// A thread attached itself to the debugger
+    AttachThread,
+    /// A thread detached itself from the debugger
+    DetachThread
+}
+/// Something that happened to a thread being debugged
+pub struct Event<'a> {
+    /// What caused the event
+    pub kind: EventKind,
+    /// The thread the event happened on
+    pub thread: ThreadId,
+    /// The function the thread stopped in, if it stopped in one
+    pub function: Option<&'a Func>,
+    /// The first value given to `insn_mark_breakpoint` where the thread stopped
+    pub data1: isize,
+    /// The second value given to `insn_mark_breakpoint` where the thread stopped
+    pub data2: isize,
+    /// The breakpoint the thread stopped at, for `HardBreakpoint` events
+    pub breakpoint: BreakpointId
+}
+/// A description of where threads should stop, where any field which is `None`
+/// matches anything
+#[derive(Clone, Copy, Default)]
+pub struct Breakpoint<'a> {
+    /// The thread to stop
+    pub thread: Option<ThreadId>,
+    /// The function to stop in
+    pub function: Option<&'a Func>,
+    /// The first value given to `insn_mark_breakpoint` to stop at
+    pub data1: Option<isize>,
+    /// The second value given to `insn_mark_breakpoint` to stop at
+    pub data2: Option<isize>
+}
+/// A debugger for the functions in a context
+///
+/// Threads attach themselves to the debugger, then stop at the places marked
+/// with `insn_mark_breakpoint` that match its breakpoints, or after being
+/// stepped. Another thread waits for these events with `wait_event`, then
+/// tells the stopped thread to carry on with `run`, `step`, `next` or `finish`.
+pub struct Debugger<'a> {
+    _debugger: jit_debugger_t,
+    marker: PhantomData<&'a ()>
+}
+// LibJIT guards the state of a debugger with its own lock, and its functions
+// are meant to be called from the thread waiting for events as well as the
+// threads being debugged, so it can be shared between threads
+unsafe impl<'a> Send for Debugger<'a> {}
+unsafe impl<'a> Sync for Debugger<'a> {}
+impl<'a> Debugger<'a> {
+    /// Check if the JIT supports debugging
+    pub fn is_possible() -> bool {
+        unsafe {
+            jit_debugging_possible() != 0
+        }
+    }
+    /// Create a debugger for the context given
+    ///
+    /// A context can only have one debugger at a time, so this panics if it
+    /// already has one.
+    pub fn new<T>(ctx: &'a Context<T>) -> Debugger<'a> {
+        unsafe {
+            if !jit_debugger_from_context(ctx.into()).is_null() {
+                panic!("Context already has a debugger")
+            }
+            let debugger = jit_debugger_create(ctx.into());
+            if debugger.is_null() {
+                oom()
+            }
+            Debugger {
+                _debugger: debugger,
+                marker: PhantomData
+            }
+        }
+    }
+    /// Get the context this debugger is debugging
+    pub fn get_context(&self) -> &'a Context {
+        unsafe {
+            jit_debugger_get_context(self._debugger).into()
+        }
+    }
+    /// Get the identifier of the current thread
+    pub fn get_self(&self) -> ThreadId {
+        unsafe {
+            ThreadId(jit_debugger_get_self(self._debugger))
+        }
+    }
+    /// Attach the current thread to the debugger, so it will stop at breakpoints,
+    /// and stop it straight away if `stop_immediately` is set
+    pub fn attach_self(&self, stop_immediately: bool) {
+        unsafe {
+            jit_debugger_attach_self(self._debugger, stop_immediately as c_int);
+        }
+    }
+    /// Detach the current thread from the debugger
+    pub fn detach_self(&self) {
+        unsafe {
+            jit_debugger_detach_self(self._debugger);
+        }
+    }
+    /// Wait for the next event, for up to `timeout` milliseconds or forever if
+    /// it is `None`, and get it if one arrived
+    pub fn wait_event(&self, timeout: Option<u32>) -> Option<Event<'a>> {
+        unsafe {
+            let mut event: jit_debugger_event_t = mem::zeroed();
+            let timeout = timeout.map(|timeout| timeout as jit_int).unwrap_or(-1);
+            if jit_debugger_wait_event(self._debugger, &mut event, timeout) == 0 {
+                return None
+            }
+            let kind = match event._type {
+                0 => EventKind::Quit,
+                1 => EventKind::HardBreakpoint,
+                2 => EventKind::SoftBreakpoint,
+                3 => EventKind::UserBreakpoint,
+                4 => EventKind::AttachThread,
+                5 => EventKind::DetachThread,
+                kind => panic!("unknown debugger event kind {}", kind)
+            };
+            Some(Event {
+                kind: kind,
+                thread: ThreadId(event.thread),
+                function: from_ptr_opt(event.function),
+                data1: event.data1 as isize,
+                data2: event.data2 as isize,
+                breakpoint: BreakpointId(event.id)
+            })
+        }
+    }
+    /// Add a breakpoint, so threads stop where it matches
+    pub fn add_breakpoint(&self, breakpoint: Breakpoint) -> BreakpointId {
+        unsafe {
+            let mut info = Struct_jit_debugger_breakpoint_info {
+                flags: 0,
+                thread: 0,
+                function: ptr::null_mut(),
+                data1: 0,
+                data2: 0
+            };
+            if let Some(ThreadId(thread)) = breakpoint.thread {
+                info.flags |= 1;
+                info.thread = thread;
+            }
+            if let Some(function) = breakpoint.function {
+                info.flags |= 2;
+                info.function = function.into();
+            }
+            if let Some(data1) = breakpoint.data1 {
+                info.flags |= 4;
+                info.data1 = data1 as jit_nint;
+            }
+            if let Some(data2) = breakpoint.data2 {
+                info.flags |= 8;
+                info.data2 = data2 as jit_nint;
+            }
+            let id = jit_debugger_add_breakpoint(self._debugger, &mut info);
+            if id == 0 {
+                oom()
+            }
+            BreakpointId(id)
+        }
+    }
+    /// Remove a breakpoint
+    pub fn remove_breakpoint(&self, id: BreakpointId) {
+        unsafe {
+            jit_debugger_remove_breakpoint(self._debugger, id.0);
+        }
+    }
+    /// Remove every breakpoint
+    pub fn remove_all_breakpoints(&self) {
+        unsafe {
+            jit_debugger_remove_all_breakpoints(self._debugger);
+        }
+    }
+    /// Check if the thread is still attached to the debugger
+    pub fn is_alive(&self, thread: ThreadId) -> bool {
+        unsafe {
+            jit_debugger_is_alive(self._debugger, thread.0) != 0
+        }
+    }
+    /// Check if the thread is running rather than stopped
+    pub fn is_running(&self, thread: ThreadId) -> bool {
+        unsafe {
+            jit_debugger_is_running(self._debugger, thread.0) != 0
+        }
+    }
+    /// Let a stopped thread run until it reaches a breakpoint
+    pub fn run(&self, thread: ThreadId) {
+        unsafe {
+            jit_debugger_run(self._debugger, thread.0);
+        }
+    }
+    /// Let a stopped thread run until the next marked position, stepping into calls
+    pub fn step(&self, thread: ThreadId) {
+        unsafe {
+            jit_debugger_step(self._debugger, thread.0);
+        }
+    }
+    /// Let a stopped thread run until the next marked position, stepping over calls
+    pub fn next(&self, thread: ThreadId) {
+        unsafe {
+            jit_debugger_next(self._debugger, thread.0);
+        }
+    }
+    /// Let a stopped thread run until it returns from the current function
+    pub fn finish(&self, thread: ThreadId) {
+        unsafe {
+            jit_debugger_finish(self._debugger, thread.0);
+        }
+    }
+    /// Stop every thread attached to the debugger
+    pub fn interrupt(&self) {
+        unsafe {
+            jit_debugger_break(self._debugger);
+        }
+    }
+    /// Tell the thread waiting for events to quit
+    pub fn quit(&self) {
+        unsafe {
+            jit_debugger_quit(self._debugger);
+        }
+    }
+    /// Set the hook that is run at every place marked with `insn_mark_breakpoint`
+    /// in the context, with the function it is in and the data it was marked with
+    ///
+    /// This is run before the debugger checks its breakpoints, so it can be used
+    /// for tracing or profiling as well as debugging. It is run on whichever
+    /// thread runs the JIT code, so it must be safe to share between threads.
+    /// The hook is removed when the debugger is dropped.
+    pub fn set_hook<F>(&self, hook: F) where F:Fn(&Func, isize, isize) + Send + Sync + 'static {
+        unsafe {
+            let context = jit_debugger_get_context(self._debugger);
+            let boxed: Box<Box<Fn(&Func, isize, isize) + Send + Sync>> = Box::new(Box::new(hook));
+            if jit_context_set_meta(context, DEBUG_HOOK, mem::transmute(boxed), Some(::free_data::<Box<Fn(&Func, isize, isize) + Send + Sync>>)) == 0 {
+                oom()
+            }
+            jit_debugger_set_hook(context, Some(call_hook));
+        }
+    }
+}
+impl<'a> Drop for Debugger<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            let context = jit_debugger_get_context(self._debugger);
+            jit_debugger_set_hook(context, None);
+            jit_context_free_meta(context, DEBUG_HOOK);
+            jit_debugger_destroy(self._debugger);
+        }
+    }
+}
+/// Run the debug hook of the context the function is in, aborting if it panics
+extern fn call_hook(func: jit_function_t, data1: jit_nint, data2: jit_nint) {
+    util::abort_on_panic(|| unsafe {
+        let hook = jit_context_get_meta(jit_function_get_context(func), DEBUG_HOOK) as *const Box<Fn(&Func, isize, isize) + Send + Sync>;
+        if !hook.is_null() {
+            (*hook)(from_ptr(func), data1 as isize, data2 as isize)
+        }
+    })
+}
@@ -0,0 +1,285 @@
+use raw::*;
+use exception::JitException;
+use from_jit::{FromJit, FromJitError};
+use types::{kind, Ty, Type};
+use std::borrow::ToOwned;
+use std::error::Error;
+use std::os::raw::{c_uint, c_void};
+use std::{fmt, mem, ptr, slice};
+/// A value whose type is only known at runtime, for passing to and from
+/// functions whose signatures are only known at runtime
+#[derive(Clone, Debug, PartialEq)]
+pub enum JitValue {
+    /// No value, which is what functions returning `void` give
+    Void,
+    /// A signed 8-bit integer
+    I8(i8),
+    /// An unsigned 8-bit integer
+    U8(u8),
+    /// A signed 16-bit integer
+    I16(i16),
+    /// An unsigned 16-bit integer
+    U16(u16),
+    /// A signed 32-bit integer
+    I32(i32),
+    /// An unsigned 32-bit integer
+    U32(u32),
+    /// A signed 64-bit integer
+    I64(i64),
+    /// An unsigned 64-bit integer
+    U64(u64),
+    /// A signed pointer-sized integer
+    ISize(isize),
+    /// An unsigned pointer-sized integer
+    USize(usize),
+    /// A 32-bit float
+    F32(f32),
+    /// A 64-bit float
+    F64(f64),
+    /// A pointer, including a function pointer
+    Ptr(*mut c_void),
+    /// A struct or union, as the bytes it is made of
+    Struct(Vec<u8>)
+}
+impl JitValue {
+    /// Check if this value can be given where a value of type `ty` is expected
+    pub fn is_of_type(&self, ty: &Ty) -> bool {
+        let ty = ty.remove_tags();
+        let kind = ty.get_kind();
+        match *self {
+            JitValue::Void => kind == kind::Void,
+            JitValue::I8(_) => kind == kind::SByte,
+            JitValue::U8(_) => kind == kind::UByte,
+            JitValue::I16(_) => kind == kind::Short,
+            JitValue::U16(_) => kind == kind::UShort,
+            JitValue::I32(_) => kind == kind::Int,
+            JitValue::U32(_) => kind == kind::UInt,
+            JitValue::I64(_) => kind == kind::Long,
+            JitValue::U64(_) => kind == kind::ULong,
+            JitValue::ISize(_) => kind == kind::NInt,
+            JitValue::USize(_) => kind == kind::NUInt,
+            JitValue::F32(_) => kind == kind::Float32,
+            JitValue::F64(_) => kind == kind::Float64,
+            JitValue::Ptr(_) => kind == kind::Pointer || kind == kind::Signature,
+            JitValue::Struct(ref bytes) =>
+                (kind == kind::Struct || kind == kind::Union) && bytes.len() == ty.get_size()
+        }
+    }
+    /// Get a pointer to the value, laid out like a value of its type in native code
+    pub fn as_ptr(&self) -> *const c_void {
+        match *self {
+            JitValue::Void => ptr::null(),
+            JitValue::I8(ref v) => v as *const i8 as *const c_void,
+            JitValue::U8(ref v) => v as *const u8 as *const c_void,
+            JitValue::I16(ref v) => v as *const i16 as *const c_void,
+            JitValue::U16(ref v) => v as *const u16 as *const c_void,
+            JitValue::I32(ref v) => v as *const i32 as *const c_void,
+            JitValue::U32(ref v) => v as *const u32 as *const c_void,
+            JitValue::I64(ref v) => v as *const i64 as *const c_void,
+            JitValue::U64(ref v) => v as *const u64 as *const c_void,
+            JitValue::ISize(ref v) => v as *const isize as *const c_void,
+            JitValue::USize(ref v) => v as *const usize as *const c_void,
+            JitValue::F32(ref v) => v as *const f32 as *const c_void,
+            JitValue::F64(ref v) => v as *const f64 as *const c_void,
+            JitValue::Ptr(ref v) => v as *const *mut c_void as *const c_void,
+            JitValue::Struct(ref bytes) => bytes.as_ptr() as *const c_void
+        }
+    }
+    /// Read this value as a Rust type, checking it has the type `ty` and
+    /// that values of `ty` can be read as that Rust type
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let ty = get::<(i32, i32)>();
+    /// let value = unsafe { JitValue::read(&ty, &(3i32, 4i32) as *const _ as *const _).unwrap() };
+    /// assert_eq!(value.decode::<(i32, i32)>(&ty), Ok((3, 4)));
+    /// assert!(JitValue::I32(3).decode::<(i32, i32)>(&ty).is_err());
+    /// ```
+    pub fn decode<T>(&self, ty: &Ty) -> Result<T, FromJitError> where T:FromJit {
+        if !self.is_of_type(ty) {
+            return Err(FromJitError::Type(ty.to_owned()))
+        }
+        unsafe {
+            T::read(ty, self.as_ptr())
+        }
+    }
+    /// Read a value of type `ty` from `ptr`, or give `None` if values of that
+    /// type can't be represented
+    pub unsafe fn read(ty: &Ty, ptr: *const c_void) -> Option<JitValue> {
+        let ty = ty.remove_tags();
+        let kind = ty.get_kind();
+        Some(match kind {
+            kind::Void => JitValue::Void,
+            kind::SByte => JitValue::I8(*(ptr as *const i8)),
+            kind::UByte => JitValue::U8(*(ptr as *const u8)),
+            kind::Short => JitValue::I16(*(ptr as *const i16)),
+            kind::UShort => JitValue::U16(*(ptr as *const u16)),
+            kind::Int => JitValue::I32(*(ptr as *const i32)),
+            kind::UInt => JitValue::U32(*(ptr as *const u32)),
+            kind::Long => JitValue::I64(*(ptr as *const i64)),
+            kind::ULong => JitValue::U64(*(ptr as *const u64)),
+            kind::NInt => JitValue::ISize(*(ptr as *const isize)),
+            kind::NUInt => JitValue::USize(*(ptr as *const usize)),
+            kind::Float32 => JitValue::F32(*(ptr as *const f32)),
+            kind::Float64 => JitValue::F64(*(ptr as *const f64)),
+            kind::Pointer | kind::Signature => JitValue::Ptr(*(ptr as *const *mut c_void)),
+            kind::Struct | kind::Union => {
+                JitValue::Struct(slice::from_raw_parts(ptr as *const u8, ty.get_size()).to_vec())
+            },
+            _ => return None
+        })
+    }
+}
+/// An error in calling a function with dynamically typed values
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApplyError {
+    /// The wrong number of arguments were given
+    ArgumentCount {
+        /// The number of parameters the function takes
+        expected: usize,
+        /// The number of arguments given
+        got: usize
+    },
+    /// An argument didn't have the type of its parameter
+    ArgumentType {
+        /// The index of the argument
+        index: usize,
+        /// The type of the parameter
+        expected: Type,
+        /// The argument given
+        got: JitValue
+    },
+    /// The function returns a type which can't be represented as a `JitValue`,
+    /// which is only `NFloat`
+    ReturnType(Type),
+    /// The function threw an exception
+    Exception(JitException)
+}
+impl From<JitException> for ApplyError {
+    fn from(exception: JitException) -> ApplyError {
+        ApplyError::Exception(exception)
+    }
+}
+impl fmt::Display for ApplyError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApplyError::ArgumentCount { expected, got } =>
+                write!(fmt, "expected {} arguments, but got {}", expected, got),
+            ApplyError::ArgumentType { index, ref expected, ref got } =>
+                write!(fmt, "argument #{} should be {:?}, but got {:?}", index, expected, got),
+            ApplyError::ReturnType(ref ty) =>
+                write!(fmt, "can't return values of type {:?}", ty),
+            ApplyError::Exception(ref exception) =>
+                write!(fmt, "{}", exception)
+        }
+    }
+}
+impl Error for ApplyError {
+    fn description(&self) -> &str {
+        match *self {
+            ApplyError::ArgumentCount { .. } => "Wrong number of arguments",
+            ApplyError::ArgumentType { .. } => "Argument has the wrong type",
+            ApplyError::ReturnType(_) => "Unsupported return type",
+            ApplyError::Exception(ref exception) => exception.description()
+        }
+    }
+}
+/// Check `args` against the parameters of `signature`, and get pointers to them
+pub fn check_args(signature: &Ty, args: &[JitValue]) -> Result<Vec<*mut c_void>, ApplyError> {
+    let expected = signature.params().count();
+    if expected != args.len() {
+        return Err(ApplyError::ArgumentCount {
+            expected: expected,
+            got: args.len()
+        })
+    }
+    for (index, (param, arg)) in signature.params().zip(args).enumerate() {
+        if !arg.is_of_type(param) {
+            return Err(ApplyError::ArgumentType {
+                index: index,
+                expected: param.to_owned(),
+                got: arg.clone()
+            })
+        }
+    }
+    Ok(args.iter().map(|arg| arg.as_ptr() as *mut c_void).collect())
+}
+/// A buffer big enough and aligned enough for the return value of a signature
+pub struct ReturnBuffer<'a> {
+    ty: &'a Ty,
+    buffer: Vec<u64>
+}
+impl<'a> ReturnBuffer<'a> {
+    /// Make a return buffer for `signature`, checking its return type can be
+    /// represented as a `JitValue`
+    ///
+    /// This gives `ReturnType` if the function returns an `NFloat`, since
+    /// there's no Rust type for the platform's long double.
+    pub fn new(signature: &'a Ty) -> Result<ReturnBuffer<'a>, ApplyError> {
+        let ty = signature.get_return().unwrap();
+        let kind = ty.remove_tags().get_kind();
+        if kind == kind::NFloat {
+            return Err(ApplyError::ReturnType(ty.to_owned()))
+        }
+        let words = (ty.get_size() + mem::size_of::<u64>() - 1) / mem::size_of::<u64>();
+        Ok(ReturnBuffer {
+            ty: ty,
+            buffer: vec![0; words + 1]
+        })
+    }
+    /// Get a pointer to the buffer to write the return value to
+    pub fn as_mut_ptr(&mut self) -> *mut c_void {
+        self.buffer.as_mut_ptr() as *mut c_void
+    }
+    /// Read the return value written to the buffer
+    pub fn read(&self) -> JitValue {
+        unsafe {
+            JitValue::read(self.ty, self.buffer.as_ptr() as *const c_void).unwrap()
+        }
+    }
+}
+/// A call to a native function through a signature only known at runtime
+///
+/// ```rust
+/// use jit::*;
+/// extern fn add(a: i32, b: i32) -> i32 {
+///     a + b
+/// }
+/// let sig = get::<fn(i32, i32) -> i32>();
+/// let call = unsafe { DynamicCall::new(add as *mut _, &sig) };
+/// assert_eq!(call.call(&[JitValue::I32(3), JitValue::I32(4)]), Ok(JitValue::I32(7)));
+/// assert!(call.call(&[JitValue::I32(3)]).is_err());
+/// ```
+pub struct DynamicCall<'a> {
+    func: *mut c_void,
+    signature: &'a Ty
+}
+impl<'a> DynamicCall<'a> {
+    /// Make a call to the native function `func`, which must have the signature given
+    ///
+    /// This panics if `signature` isn't a function signature.
+    pub unsafe fn new(func: *mut c_void, signature: &'a Ty) -> DynamicCall<'a> {
+        if !signature.is_signature() {
+            panic!("{:?} is not a function signature", signature)
+        }
+        DynamicCall {
+            func: func,
+            signature: signature
+        }
+    }
+    /// Get the signature of the function
+    pub fn get_signature(&self) -> &'a Ty {
+        self.signature
+    }
+    /// Call the function with `args`, checking they match its signature
+    ///
+    /// This gives `ReturnType` without calling it if it returns an `NFloat`.
+    pub fn call(&self, args: &[JitValue]) -> Result<JitValue, ApplyError> {
+        let mut args = try!(check_args(self.signature, args));
+        let mut ret = try!(ReturnBuffer::new(self.signature));
+        unsafe {
+            jit_apply(self.signature.into(), self.func, args.as_mut_ptr(), args.len() as c_uint, ret.as_mut_ptr());
+        }
+        Ok(ret.read())
+    }
+}
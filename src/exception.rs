@@ -0,0 +1,153 @@
+use raw::*;
+use std::os::raw::{c_int, c_void};
+use std::error::Error;
+use std::fmt;
+/// An exception that LibJIT throws by itself
+#[repr(i32)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinException {
+    /// Overflow during a checked arithmetic operation
+    Overflow = 0,
+    /// Arithmetic exception, like dividing the minimum integer by -1
+    Arithmetic = -1,
+    /// Division by zero
+    DivisionByZero = -2,
+    /// Error during function compilation
+    CompileError = -3,
+    /// Out of memory
+    OutOfMemory = -4,
+    /// Null pointer dereferenced
+    NullReference = -5,
+    /// Null function pointer called
+    NullFunction = -6,
+    /// Nested function called from a non-nested context
+    CalledNested = -7,
+    /// Array index out of bounds
+    OutOfBounds = -8,
+    /// Undefined label
+    UndefinedLabel = -9,
+    /// Memory full
+    MemoryFull = -10000
+}
+/// Every builtin exception, which the exception handler gives the addresses of
+/// as the objects to throw
+static BUILTINS: [BuiltinException; 11] = [
+    BuiltinException::Overflow,
+    BuiltinException::Arithmetic,
+    BuiltinException::DivisionByZero,
+    BuiltinException::CompileError,
+    BuiltinException::OutOfMemory,
+    BuiltinException::NullReference,
+    BuiltinException::NullFunction,
+    BuiltinException::CalledNested,
+    BuiltinException::OutOfBounds,
+    BuiltinException::UndefinedLabel,
+    BuiltinException::MemoryFull
+];
+impl fmt::Display for BuiltinException {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.description())
+    }
+}
+impl fmt::Debug for BuiltinException {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.description())
+    }
+}
+impl Error for BuiltinException {
+    fn description(&self) -> &'static str {
+        match *self {
+            BuiltinException::Overflow => "Overflow during checked arithmetic operation",
+            BuiltinException::Arithmetic => "Arithmetic exception (dividing the minimum integer by -1)",
+            BuiltinException::DivisionByZero => "Division by zero",
+            BuiltinException::CompileError => "Error during function compilation",
+            BuiltinException::OutOfMemory => "Out of memory",
+            BuiltinException::NullReference => "Null pointer dereferenced",
+            BuiltinException::NullFunction => "Null function pointer called",
+            BuiltinException::CalledNested => "Nested function called from non-nested context",
+            BuiltinException::OutOfBounds => "Array index out of bounds",
+            BuiltinException::UndefinedLabel => "Undefined label",
+            BuiltinException::MemoryFull => "Memory full"
+        }
+    }
+}
+impl BuiltinException {
+    /// Get the builtin exception with the code given
+    pub fn from_code(code: c_int) -> Option<BuiltinException> {
+        BUILTINS.iter().find(|&&builtin| builtin as c_int == code).cloned()
+    }
+}
+
+/// An exception thrown by JIT code that wasn't caught inside it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum JitException {
+    /// An exception that LibJIT threw by itself
+    Builtin(BuiltinException),
+    /// An object thrown by `insn_throw`
+    Object(*mut c_void)
+}
+impl From<*mut c_void> for JitException {
+    /// Convert from a thrown object
+    fn from(ptr: *mut c_void) -> JitException {
+        match BUILTINS.iter().find(|&builtin| builtin as *const BuiltinException as *mut c_void == ptr) {
+            Some(&builtin) => JitException::Builtin(builtin),
+            None => JitException::Object(ptr)
+        }
+    }
+}
+impl fmt::Display for JitException {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JitException::Builtin(builtin) => write!(fmt, "{}", builtin),
+            JitException::Object(ptr) => write!(fmt, "JIT code threw {:?}", ptr)
+        }
+    }
+}
+impl fmt::Debug for JitException {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+impl Error for JitException {
+    fn description(&self) -> &str {
+        match *self {
+            JitException::Builtin(ref builtin) => builtin.description(),
+            JitException::Object(_) => "JIT code threw an exception"
+        }
+    }
+}
+/// Turn a builtin exception code into an object to throw, so it can be caught
+/// instead of exiting the process
+extern fn throw_builtin(code: c_int) -> *mut c_void {
+    builtin_object(BuiltinException::from_code(code).unwrap_or(BuiltinException::MemoryFull))
+}
+/// Get the object that is thrown for a builtin exception
+pub fn builtin_object(builtin: BuiltinException) -> *mut c_void {
+    BUILTINS.iter().find(|&&other| other == builtin).unwrap() as *const BuiltinException as *mut c_void
+}
+/// Make builtin exceptions thrown on this thread catchable, unless the thread
+/// already has an exception handler
+pub fn init_handler() {
+    unsafe {
+        if jit_exception_get_handler().is_none() {
+            jit_exception_set_handler(Some(throw_builtin));
+        }
+    }
+}
+/// Run `cb`, which gives `false` when the JIT code it ran threw an exception,
+/// and get the exception that was thrown
+///
+/// The handler that makes builtin exceptions catchable is swapped in for the
+/// call, since handlers are per thread and this can run on any thread.
+pub fn catch<F>(cb: F) -> Result<(), JitException> where F:FnOnce() -> bool {
+    unsafe {
+        let old_handler = jit_exception_set_handler(Some(throw_builtin));
+        let ok = cb();
+        jit_exception_set_handler(old_handler);
+        if ok {
+            Ok(())
+        } else {
+            Err(JitException::from(jit_exception_get_last_and_clear()))
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use dynamic::JitValue;
+use function::UncompiledFunction;
+use value::Val;
+/// An arithmetic operation on two values which can be folded when both are constants
+#[derive(Clone, Copy)]
+pub enum BinOp {
+    /// Addition, wrapping on overflow
+    Add,
+    /// Subtraction, wrapping on overflow
+    Sub,
+    /// Multiplication, wrapping on overflow
+    Mul,
+    /// Division
+    Div,
+    /// Remainder
+    Rem,
+    /// Bitwise and
+    And,
+    /// Bitwise or
+    Or,
+    /// Bitwise xor
+    Xor
+}
+/// A type whose values can be folded together
+trait Fold: Sized {
+    /// Do `op` on `a` and `b`, or give `None` if it should be left to run
+    /// natively, like when it would throw an exception
+    fn fold(op: BinOp, a: Self, b: Self) -> Option<Self>;
+}
+fold_ints!(i32, u32, i64, u64);
+fold_floats!(f32, f64);
+/// Fold `op` on the constants `a` and `b` into a constant in `func`, or give
+/// `None` if they can't be folded
+///
+/// Only constants of the same type are folded, and only types LibJIT doesn't
+/// promote when doing arithmetic on them, so the constant has the type the
+/// instruction would have given.
+pub fn fold<'a>(func: &'a UncompiledFunction, op: BinOp, a: &Val, b: &Val) -> Option<&'a Val> {
+    let (a, b) = match (a.as_constant(), b.as_constant()) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return None
+    };
+    match (a, b) {
+        (JitValue::I32(a), JitValue::I32(b)) => i32::fold(op, a, b).map(|v| func.insn_of(v)),
+        (JitValue::U32(a), JitValue::U32(b)) => u32::fold(op, a, b).map(|v| func.insn_of(v)),
+        (JitValue::I64(a), JitValue::I64(b)) => i64::fold(op, a, b).map(|v| func.insn_of(v)),
+        (JitValue::U64(a), JitValue::U64(b)) => u64::fold(op, a, b).map(|v| func.insn_of(v)),
+        (JitValue::F32(a), JitValue::F32(b)) => f32::fold(op, a, b).map(|v| func.insn_of(v)),
+        (JitValue::F64(a), JitValue::F64(b)) => f64::fold(op, a, b).map(|v| func.insn_of(v)),
+        _ => None
+    }
+}
@@ -0,0 +1,151 @@
+use types::{kind, Ty, Type};
+use std::borrow::ToOwned;
+use std::error::Error;
+use std::os::raw::c_void;
+use std::{fmt, ptr};
+/// A type that can be read back from a value laid out by LibJIT
+///
+/// Values are read using the layout of the LibJIT type they were made with,
+/// so a tuple is read field by field from the offsets LibJIT gave them, even
+/// if Rust would lay the tuple out differently.
+///
+/// ```rust
+/// use jit::*;
+/// let ty = get::<(u8, f64)>();
+/// let mut bytes = vec![0; ty.get_size()];
+/// bytes[0] = 7;
+/// assert_eq!(<(u8, f64)>::from_bytes(&ty, &bytes), Ok((7, 0.0)));
+/// assert!(<(u8, u8)>::from_bytes(&ty, &bytes).is_err());
+/// ```
+pub trait FromJit: Sized {
+    /// Check if values of the LibJIT type `ty` can be read as this type
+    fn is_type(ty: &Ty) -> bool;
+    /// Read a value of the LibJIT type `ty` from `ptr`, without checking `ty`
+    /// can be read as this type
+    unsafe fn read_unchecked(ty: &Ty, ptr: *const c_void) -> Self;
+    /// Read a value of the LibJIT type `ty` from `ptr`
+    unsafe fn read(ty: &Ty, ptr: *const c_void) -> Result<Self, FromJitError> {
+        if Self::is_type(ty) {
+            Ok(Self::read_unchecked(ty, ptr))
+        } else {
+            Err(FromJitError::Type(ty.to_owned()))
+        }
+    }
+    /// Read a value of the LibJIT type `ty` from the start of `bytes`
+    fn from_bytes(ty: &Ty, bytes: &[u8]) -> Result<Self, FromJitError> {
+        let size = ty.get_size();
+        if bytes.len() < size {
+            return Err(FromJitError::Size {
+                expected: size,
+                got: bytes.len()
+            })
+        }
+        unsafe {
+            Self::read(ty, bytes.as_ptr() as *const c_void)
+        }
+    }
+}
+/// An error in reading a value back from LibJIT
+#[derive(Clone, Debug, PartialEq)]
+pub enum FromJitError {
+    /// The value's type can't be read as the type asked for
+    Type(Type),
+    /// The buffer given is too small to hold a value of the type
+    Size {
+        /// The size of the type
+        expected: usize,
+        /// The size of the buffer
+        got: usize
+    }
+}
+impl fmt::Display for FromJitError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FromJitError::Type(ref ty) =>
+                write!(fmt, "values of type {:?} can't be read as this type", ty),
+            FromJitError::Size { expected, got } =>
+                write!(fmt, "expected {} bytes, but got {}", expected, got)
+        }
+    }
+}
+impl Error for FromJitError {
+    fn description(&self) -> &str {
+        match *self {
+            FromJitError::Type(_) => "Value has the wrong type",
+            FromJitError::Size { .. } => "Buffer is too small"
+        }
+    }
+}
+/// Offset `ptr` by `offset` bytes
+#[inline(always)]
+unsafe fn offset(ptr: *const c_void, offset: usize) -> *const c_void {
+    (ptr as *const u8).offset(offset as isize) as *const c_void
+}
+impl FromJit for () {
+    #[inline(always)]
+    fn is_type(ty: &Ty) -> bool {
+        ty.remove_tags().get_kind() == kind::Void
+    }
+    #[inline(always)]
+    unsafe fn read_unchecked(_: &Ty, _: *const c_void) -> () {
+        ()
+    }
+}
+impl FromJit for bool {
+    #[inline(always)]
+    fn is_type(ty: &Ty) -> bool {
+        let kind = ty.remove_tags().get_kind();
+        kind == kind::UByte || kind == kind::SByte
+    }
+    #[inline(always)]
+    unsafe fn read_unchecked(_: &Ty, ptr: *const c_void) -> bool {
+        *(ptr as *const u8) != 0
+    }
+}
+from_jit_prims!{
+    f64 => Float64,
+    f32 => Float32,
+    isize => NInt,
+    usize => NUInt,
+    i64 => Long,
+    u64 => ULong,
+    i32 => Int,
+    u32 => UInt,
+    i16 => Short,
+    u16 => UShort,
+    i8 => SByte,
+    u8 => UByte
+}
+impl<T> FromJit for *const T {
+    #[inline(always)]
+    fn is_type(ty: &Ty) -> bool {
+        ty.remove_tags().get_kind() == kind::Pointer
+    }
+    #[inline(always)]
+    unsafe fn read_unchecked(_: &Ty, ptr: *const c_void) -> *const T {
+        ptr::read_unaligned(ptr as *const *const T)
+    }
+}
+impl<T> FromJit for *mut T {
+    #[inline(always)]
+    fn is_type(ty: &Ty) -> bool {
+        ty.remove_tags().get_kind() == kind::Pointer
+    }
+    #[inline(always)]
+    unsafe fn read_unchecked(_: &Ty, ptr: *const c_void) -> *mut T {
+        ptr::read_unaligned(ptr as *const *mut T)
+    }
+}
+from_jit_tuple!(A, B);
+from_jit_tuple!(A, B, C);
+from_jit_tuple!(A, B, C, D);
+from_jit_tuple!(A, B, C, D, E);
+from_jit_tuple!(A, B, C, D, E, F);
+from_jit_tuple!(A, B, C, D, E, F, G);
+from_jit_tuple!(A, B, C, D, E, F, G, H);
+from_jit_tuple!(A, B, C, D, E, F, G, H, I);
+from_jit_tuple!(A, B, C, D, E, F, G, H, I, J);
+from_jit_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+from_jit_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+from_jit_arrays!(0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+                 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32);
@@ -1,11 +1,15 @@
 use raw::*;
-use context::{Context, ContextMember};
+use context::{self, Context, ContextMember, Location};
+use dynamic::{self, ApplyError, JitValue, ReturnBuffer};
 use compile::Compile;
+use exception::{self, BuiltinException, JitException};
+use typed::{self, Args};
+use fold::{self, BinOp};
 use label::Label;
 use types::{Ty, Type, Field};
 use insn::Block;
 use value::Val;
-use util::{self, CString, from_ptr, from_ptr_opt};
+use util::{self, CString, from_ptr, from_ptr_opt, oom};
 use cbox::{CSemiBox, DisposeRef};
 use std::os::raw::{
     c_int,
@@ -17,6 +21,8 @@ use std::any::Any;
 use std::default::Default;
 use std::fmt;
 use std::ops::{Deref, DerefMut, Index};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::{mem, ptr};
 
 use std::marker::PhantomData;
@@ -56,6 +62,24 @@ std::os::raw::c_int;
         }
     );
 }
+/// The function metadata key holding the label of the next catch dispatch block
+const CATCH_DISPATCH: c_int = 1;
+/// The function metadata key holding the labels of the finally blocks around
+/// the code being built
+const FINALLY_BLOCKS: c_int = 2;
+/// The function metadata key holding the name of the function
+const FUNCTION_NAME: c_int = 3;
+/// The function metadata key holding the closure that builds an on-demand function
+const ON_DEMAND: c_int = 4;
+/// The function metadata key holding the call counter of a tiered function
+const TIERING: c_int = 5;
+/// The call counter and builder of a function compiled in tiers
+struct Tiering {
+    calls: AtomicUsize,
+    promoted: AtomicBool,
+    threshold: usize,
+    builder: Box<Fn(&UncompiledFunction)>
+}
 /// A function
 pub struct Func(PhantomData<[()]>);
 native_ref!(&Func = jit_function_t);
@@ -73,6 +97,94 @@ impl Func {
     pub fn get_signature(&self) -> &Ty {
         unsafe { from_ptr(jit_function_get_signature(self.into())) }
     }
+    /// Check if the given function can be rebuilt after it has been compiled
+    pub fn is_recompilable(&self) -> bool {
+        unsafe { jit_function_is_recompilable(self.into()) != 0 }
+    }
+    /// Get the name of the given function, if it has been given one
+    pub fn get_name(&self) -> Option<&str> {
+        unsafe {
+            let name: *const String = jit_function_get_meta(self.into(), FUNCTION_NAME) as *const String;
+            if name.is_null() {
+                None
+            } else {
+                Some(&*name)
+            }
+        }
+    }
+    /// Set the name of the given function, which is shown in stack traces
+    pub fn set_name(&self, name: &str) {
+        unsafe {
+            let boxed = Box::new(String::from(name));
+            if jit_function_set_meta(self.into(), FUNCTION_NAME, mem::transmute(boxed), Some(::free_data::<String>), 0) == 0 {
+                oom()
+            }
+        }
+    }
+    /// Replace the body of the given function with the one built by `builder`
+    /// and compile it, so everything that calls it through `insn_call` or
+    /// `to_closure` runs the new body from then on
+    ///
+    /// The new body is built for the signature the function already has, which
+    /// is checked again once `builder` has run. This gives `CompileError` if it
+    /// couldn't be compiled, in which case the old body keeps running, and
+    /// panics if the function isn't compiled yet or isn't recompilable.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let mut ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&mut ctx, &get::<fn() -> i32>());
+    /// func.set_recompilable();
+    /// func.insn_return(func.insn_of(1i32));
+    /// let func = UncompiledFunction::compile(func);
+    /// assert_eq!(func.rebuild(|func| func.insn_return(func.insn_of(2i32))), Ok(()));
+    /// assert_eq!(func.try_call::<(), i32>(()), Ok(2));
+    /// ```
+    pub fn rebuild<F>(&self, builder: F) -> Result<(), BuiltinException> where F:FnOnce(&UncompiledFunction) {
+        let signature = self.get_signature().to_owned();
+        self.rebuild_checked(&signature, builder)
+    }
+    /// Replace the body of the given function like `rebuild`, checking that
+    /// `signature` is the signature it has before and after `builder` runs,
+    /// since that can't change
+    ///
+    /// This panics if the function isn't compiled yet or isn't recompilable,
+    /// or the signatures differ.
+    pub fn rebuild_as<F>(&self, signature: &Ty, builder: F) -> Result<(), BuiltinException> where F:FnOnce(&UncompiledFunction) {
+        self.check_signature(signature);
+        self.rebuild_checked(signature, builder)
+    }
+    /// Rebuild the function with `builder`, checking its signature is still
+    /// `signature` before compiling the new body
+    fn rebuild_checked<F>(&self, signature: &Ty, builder: F) -> Result<(), BuiltinException> where F:FnOnce(&UncompiledFunction) {
+        if !self.is_compiled() {
+            panic!("Function {:?} has not been compiled yet", self.get_name())
+        }
+        if !self.is_recompilable() {
+            panic!("Function {:?} is not recompilable", self.get_name())
+        }
+        unsafe {
+            let func: &UncompiledFunction = from_ptr(self.into());
+            builder(func);
+            self.check_signature(signature);
+            func.end_dispatch();
+            if jit_function_compile(self.into()) == 0 {
+                Err(BuiltinException::CompileError)
+            } else {
+                Ok(())
+            }
+        }
+    }
+    /// Panic if the function's signature isn't `signature`
+    fn check_signature(&self, signature: &Ty) {
+        let current = self.get_signature();
+        let same = current.params().count() == signature.params().count()
+            && current.params().zip(signature.params()).all(|(a, b)| a == b)
+            && current.get_return() == signature.get_return();
+        if !same {
+            panic!("Cannot rebuild function {:?} with signature {:?} as {:?}", self.get_name(), current, signature)
+        }
+    }
 }
 
 /// A function which has already been compiled from an `UncompiledFunction`, so it can
@@ -129,6 +241,79 @@ impl CompiledFunction {
             jit_function_apply(self.into(), nargs.as_mut_ptr(), ret as *mut R as *mut c_void);
         }
     }
+    /// Run the compiled function with several arguments, giving the exception
+    /// it throws if it throws one instead of aborting.
+    pub fn try_apply<'a, R>(&'a self, args: &[&Any], ret: &mut R) -> Result<(), JitException> where R: Compile<'a> {
+        let sig = self.get_signature();
+        let ret_t: Option<Type> = sig.get_return().map(|x| x.to_owned());
+        let r: Type = ::get::<R>().into_owned();
+        let num_sig_args = sig.params().count();
+        assert!(args.len() == num_sig_args, "{:?} expects {} args, but got {}", sig, num_sig_args, args.len());
+        assert!(ret_t.as_ref() == Some(&r), "{:?} returns {:?}, but got {:?}", sig, ret_t, r);
+        unsafe {
+            let mut nargs:Vec<_> = args.iter().map(|v| {
+                let obj: TraitObject = mem::transmute(*v);
+                obj.data as *mut c_void
+            }).collect();
+            exception::catch(|| {
+                jit_function_apply(self.into(), nargs.as_mut_ptr(), ret as *mut R as *mut c_void) != 0
+            })
+        }
+    }
+    /// Run the compiled function with arguments whose types are only known at
+    /// runtime, checking them against its signature and giving its return value
+    /// or the exception it throws
+    ///
+    /// Functions returning an `NFloat` can't be called like this, since there's
+    /// no Rust type for the platform's long double, so they give `ReturnType`.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&ctx, &get::<fn(i32, u8) -> i32>());
+    /// func.insn_return(func.insn_add(&func[0], &func[1]));
+    /// let func = UncompiledFunction::compile(func);
+    /// assert_eq!(func.apply_dynamic(&[JitValue::I32(1), JitValue::U8(2)]), Ok(JitValue::I32(3)));
+    /// assert!(func.apply_dynamic(&[JitValue::I32(1), JitValue::I32(2)]).is_err());
+    /// ```
+    pub fn apply_dynamic(&self, args: &[JitValue]) -> Result<JitValue, ApplyError> {
+        let sig = self.get_signature();
+        let mut args = try!(dynamic::check_args(sig, args));
+        let mut ret = try!(ReturnBuffer::new(sig));
+        try!(exception::catch(|| unsafe {
+            jit_function_apply(self.into(), args.as_mut_ptr(), ret.as_mut_ptr()) != 0
+        }));
+        Ok(ret.read())
+    }
+    /// Call the compiled function with the arguments given as a tuple, giving
+    /// the exception it throws if it throws one instead of aborting.
+    ///
+    /// This panics if the function's signature doesn't take the arguments `A`
+    /// and return `R`.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let mut ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&mut ctx, &get::<fn(i32, i32) -> i32>());
+    /// func.insn_return(func.insn_div(&func[0], &func[1]));
+    /// let func = UncompiledFunction::compile(func);
+    /// assert_eq!(func.try_call::<(i32, i32), i32>((12, 4)), Ok(3));
+    /// assert_eq!(func.try_call::<(i32, i32), i32>((1, 0)),
+    ///            Err(JitException::Builtin(BuiltinException::DivisionByZero)));
+    /// ```
+    pub fn try_call<'a, A, R>(&'a self, args: A) -> Result<R, JitException> where A: Args<'a>, R: Compile<'a> {
+        typed::assert_sig::<A, R>(self.get_signature());
+        // the vector's buffer is aligned for `R`, and only holds one once the
+        // function has returned normally
+        let mut ret: Vec<R> = Vec::with_capacity(1);
+        try!(args.with_ptrs(|args| exception::catch(|| unsafe {
+            jit_function_apply(self.into(), args.as_mut_ptr(), ret.as_mut_ptr() as *mut c_void) != 0
+        })));
+        unsafe {
+            ret.set_len(1);
+        }
+        Ok(ret.pop().unwrap())
+    }
 }
 
 macro_rules! expect(
@@ -242,6 +427,7 @@ impl UncompiledFunction {
     /// let func = UncompiledFunction::new(&mut ctx, &get::<fn(f64) -> f64>());
     /// ```
     pub fn new<'a, T>(context:&'a Context<T>, signature:&Ty) -> CSemiBox<'a, UncompiledFunction> {
+        context::use_memory(context.into());
         unsafe {
             CSemiBox::new(jit_function_create(
                 context.into(),
@@ -261,6 +447,7 @@ impl UncompiledFunction {
     /// is compiled before its parent.
     pub fn new_nested<'a, T>(context:&'a Context<T>, signature: &Ty,
                         parent: &'a UncompiledFunction) -> CSemiBox<'a, UncompiledFunction> {
+        context::use_memory(context.into());
         unsafe {
             CSemiBox::new(jit_function_create_nested(
                 context.into(),
@@ -269,6 +456,97 @@ impl UncompiledFunction {
             ))
         }
     }
+    /// Create a new function and associate it with a JIT context, with its body
+    /// built by `builder` the first time it is called.
+    ///
+    /// The `CompiledFunction` returned isn't compiled yet, so `is_compiled`
+    /// gives `false` until then. Its entry point is the on-demand trampoline,
+    /// which runs the on-demand driver of the context to build and compile it
+    /// the first time it is entered, so it can be called through `insn_call`,
+    /// `to_closure` or `try_call` like any other compiled function. If the
+    /// builder panics, the call throws `BuiltinException::CompileError` instead.
+    /// The driver can be replaced with `Context::set_on_demand_driver`.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let mut ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new_on_demand(&mut ctx, &get::<fn(i32) -> i32>(), |func| {
+    ///     func.insn_return(func.insn_mul(&func[0], &func[0]));
+    /// });
+    /// assert!(!func.is_compiled());
+    /// let square = CompiledFunction::to_closure::<i32, i32>(func);
+    /// assert_eq!(square(7), 49);
+    /// ```
+    pub fn new_on_demand<'a, T, F>(context:&'a Context<T>, signature:&Ty, builder: F) -> CSemiBox<'a, CompiledFunction>
+        where F:FnOnce(&UncompiledFunction) + 'static {
+        context::use_memory(context.into());
+        unsafe {
+            let func = jit_function_create(context.into(), signature.into());
+            let mut builder = Some(builder);
+            let boxed: Box<Box<FnMut(&UncompiledFunction)>> = Box::new(Box::new(move |func: &UncompiledFunction| {
+                if let Some(builder) = builder.take() {
+                    builder(func)
+                }
+            }));
+            if jit_function_set_meta(func, ON_DEMAND, mem::transmute(boxed), Some(::free_data::<Box<FnMut(&UncompiledFunction)>>), 0) == 0 {
+                oom()
+            }
+            jit_function_set_on_demand_compiler(func, Some(build_on_demand));
+            CSemiBox::new(func)
+        }
+    }
+    /// Create a new function and associate it with a JIT context, with its body
+    /// built by `builder` at optimization level 0, then built again at the max
+    /// optimization level once it has been called `threshold` times.
+    ///
+    /// The function is recompilable, so callers that use `insn_call` or
+    /// `to_closure` pick up the optimized code as soon as it is swapped in. The
+    /// call that reaches the threshold still runs the unoptimized code. Calls
+    /// are counted atomically, so only one caller builds it again, and if that
+    /// build fails or `builder` panics, the unoptimized code keeps running. If
+    /// `threshold` is 0, it is built at the max optimization level straight away.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let mut ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new_tiered(&mut ctx, &get::<fn(i32) -> i32>(), 2, |func| {
+    ///     func.insn_return(func.insn_add(&func[0], &func[0]));
+    /// });
+    /// let double = CompiledFunction::to_closure::<i32, i32>(func);
+    /// for i in 0..4 {
+    ///     assert_eq!(double(i), i * 2);
+    /// }
+    /// ```
+    pub fn new_tiered<'a, T, F>(context:&'a Context<T>, signature:&Ty, threshold: usize, builder: F) -> CSemiBox<'a, CompiledFunction>
+        where F:Fn(&UncompiledFunction) + 'static {
+        let func = UncompiledFunction::new(context, signature);
+        func.set_recompilable();
+        if threshold == 0 {
+            func.set_optimization_level(UncompiledFunction::get_max_optimization_level());
+            builder(&func);
+            return UncompiledFunction::compile(func)
+        }
+        func.set_optimization_level(0);
+        let tiering = Box::new(Tiering {
+            calls: AtomicUsize::new(0),
+            promoted: AtomicBool::new(false),
+            threshold: threshold,
+            builder: Box::new(builder)
+        });
+        unsafe {
+            // this is freed along with the function rather than once it has
+            // been promoted, since the unoptimized body may still be running
+            if jit_function_set_meta((&*func).into(), TIERING, mem::transmute(tiering), Some(::free_data::<Tiering>), 0) == 0 {
+                oom()
+            }
+            let ptr: jit_function_t = (&*func).into();
+            func.insn_call_native(Some("count_call"), count_call as *mut (), &::get::<fn(*mut ())>(),
+                &[func.insn_of(ptr as *mut ())], flags::NO_THROW);
+            let tiering = jit_function_get_meta(ptr, TIERING) as *const Tiering;
+            ((*tiering).builder)(&func);
+        }
+        UncompiledFunction::compile(func)
+    }
     #[inline(always)]
     /// Make an instruction to check if the `value` is a null value, and throw an exception if it is.
     pub fn insn_check_null(&self, value: &Val) {
@@ -318,15 +596,116 @@ impl UncompiledFunction {
         }
     }
     #[inline(always)]
+    /// Make an instruction to throw a builtin exception, which is caught like the
+    /// ones LibJIT throws by itself
+    pub fn insn_throw_builtin(&self, builtin: BuiltinException) {
+        self.insn_throw(self.insn_of(exception::builtin_object(builtin) as *mut ()))
+    }
+    #[inline(always)]
+    /// Get the value that holds the most recent exception thrown in this function
+    pub fn insn_thrown_exception(&self) -> &Val {
+        unsafe {
+            from_ptr(jit_insn_thrown_exception(self.into()))
+        }
+    }
+    #[inline(always)]
+    /// Make an instruction to rethrow the current exception to the caller of
+    /// this function
+    pub fn insn_rethrow_unhandled(&self) {
+        unsafe {
+            jit_insn_rethrow_unhandled(self.into());
+        }
+    }
+    #[inline(always)]
+    /// Make an instruction that gets the current position in the code and the
+    /// functions on the call stack, which can be turned into a `StackTrace`
+    ///
+    /// This is normally used just before `insn_throw` to record where the
+    /// exception was thrown from.
+    pub fn insn_get_call_stack(&self) -> &Val {
+        unsafe {
+            from_ptr(jit_insn_get_call_stack(self.into()))
+        }
+    }
+    #[inline(always)]
+    /// Mark the current position in the function with the bytecode offset given,
+    /// which stack traces give for the code after it
+    ///
+    /// # Panics
+    ///
+    /// If `offset` is negative, since those offsets are used for the locations
+    /// marked by `insn_mark_location`.
+    pub fn insn_mark_offset(&self, offset: i32) {
+        assert!(offset >= 0, "negative bytecode offset {}", offset);
+        unsafe {
+            jit_insn_mark_offset(self.into(), offset);
+        }
+    }
+    #[inline(always)]
+    /// Mark the current position in the function as a breakpoint, which runs the
+    /// debug hook and stops threads attached to a `Debugger` if it matches one
+    /// of its breakpoints
+    ///
+    /// Values of `data1` below `marks::FIRST` are free for the program to use,
+    /// and the rest have the meanings given in `marks`.
+    pub fn insn_mark_breakpoint(&self, data1: isize, data2: isize) {
+        unsafe {
+            jit_insn_mark_breakpoint(self.into(), data1 as jit_nint, data2 as jit_nint);
+        }
+    }
+    #[inline(always)]
+    /// Mark the current position in the function as a breakpoint, like
+    /// `insn_mark_breakpoint`, with data computed at runtime
+    pub fn insn_mark_breakpoint_variable(&self, data1: &Val, data2: &Val) {
+        unsafe {
+            jit_insn_mark_breakpoint_variable(self.into(), data1.into(), data2.into());
+        }
+    }
+    /// Mark the current position in the function as compiled from the source
+    /// location given, so it can be found from the frames of stack traces and
+    /// from native program counters with `Context::location_at`
+    ///
+    /// Locations are marked with negative bytecode offsets, so the code after
+    /// this has no offset from `insn_mark_offset` until it marks one again.
+    pub fn insn_mark_location(&self, file: &str, line: u32, column: u32) {
+        let location = Location {
+            file: String::from(file),
+            line: line,
+            column: column
+        };
+        let locations = context::locations(self.get_context().into());
+        if locations.last() != Some(&location) {
+            locations.push(location);
+        }
+        let offset = context::location_offset(locations.len() - 1);
+        unsafe {
+            jit_insn_mark_offset(self.into(), offset);
+        }
+    }
+    #[inline(always)]
     /// Make an instruction that will return from the function with the value given
+    ///
+    /// If this is inside the body of a `build_try_finally`, the finally blocks are
+    /// run before returning.
     pub fn insn_return(&self, retval: &Val) {
+        let retval = if !self.in_finally_body() {
+            retval
+        } else {
+            let retval = self.insn_dup(retval);
+            self.insn_call_finally_blocks();
+            retval
+        };
         unsafe {
             jit_insn_return(self.into(), retval.into());
         }
     }
     #[inline(always)]
     /// Return from the function
+    ///
+    /// If this is inside the body of a `build_try_finally`, the finally blocks are
+    /// run before returning.
     pub fn insn_default_return(&self) {
+        self.insn_call_finally_blocks();
         unsafe {
             jit_insn_default_return(self.into());
         }
@@ -334,7 +713,7 @@ impl UncompiledFunction {
     #[inline(always)]
     /// Make an instruction that multiplies the values
     pub fn insn_mul(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_mul)
+        self.insn_folding_binop(v1, v2, BinOp::Mul, jit_insn_mul)
     }
     #[inline(always)]
     /// Make an instruction that multiplies the values and throws upon overflow
@@ -347,7 +726,7 @@ impl UncompiledFunction {
     /// You can also just use `v1 + v2` in your code instead of running this method,
     /// `&Val` has the `Add` trait implemented so it can be added with normal operators.
     pub fn insn_add(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_add)
+        self.insn_folding_binop(v1, v2, BinOp::Add, jit_insn_add)
     }
     #[inline(always)]
     /// Make an instruction that adds the values and throws upon overflow
@@ -367,7 +746,7 @@ impl UncompiledFunction {
     /// You can also just use `v1 - v2` in your code instead of running this method,
     /// `&Val` has the `Sub` trait implemented so it can be subtracted with normal operators.
     pub fn insn_sub(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_sub)
+        self.insn_folding_binop(v1, v2, BinOp::Sub, jit_insn_sub)
     }
     #[inline(always)]
     /// Make an instruction that subtracts the second value from the first and throws upon overflow
@@ -380,7 +759,7 @@ impl UncompiledFunction {
     /// You can also just use `v1 / v2` in your code instead of running this method,
     /// `&Val` has the `Div` trait implemented so it can be divided with normal operators.
     pub fn insn_div(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_div)
+        self.insn_folding_binop(v1, v2, BinOp::Div, jit_insn_div)
     }
     #[inline(always)]
     /// Make an instruction that finds the remainder when the first number is
@@ -389,7 +768,7 @@ impl UncompiledFunction {
     /// You can also just use `v1 % v2` in your code instead of running this method,
     /// `&Val` has the `Rem` trait implemented so it can be done with normal operators.
     pub fn insn_rem(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_rem)
+        self.insn_folding_binop(v1, v2, BinOp::Rem, jit_insn_rem)
     }
     #[inline(always)]
     /// Make an instruction that checks if the first value is lower than or
@@ -429,7 +808,7 @@ impl UncompiledFunction {
     /// You can also just use `v1 & v2` in your code instead of running this method,
     /// `&Val` has the `BitAnd` trait implemented so it can be done with normal operators.
     pub fn insn_and(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_and)
+        self.insn_folding_binop(v1, v2, BinOp::And, jit_insn_and)
     }
     #[inline(always)]
     /// Make an instruction that performs a bitwise or on the two values
@@ -437,7 +816,7 @@ impl UncompiledFunction {
     /// You can also just use `v1 | v2` in your code instead of running this method,
     /// `&Val` has the `BitOr` trait implemented so it can be done with normal operators.
     pub fn insn_or(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_or)
+        self.insn_folding_binop(v1, v2, BinOp::Or, jit_insn_or)
     }
     #[inline(always)]
     /// Make an instruction that performs a bitwise xor on the two values
@@ -445,7 +824,7 @@ impl UncompiledFunction {
     /// You can also just use `v1 ^ v2` in your code instead of running this method,
     /// `&Val` has the `BitXor` trait implemented so it can be done with normal operators.
     pub fn insn_xor(&self, v1: &Val, v2: &Val) -> &Val {
-        self.insn_binop(v1, v2, jit_insn_xor)
+        self.insn_folding_binop(v1, v2, BinOp::Xor, jit_insn_xor)
     }
     #[inline(always)]
     /// Make an instruction that performs a bitwise not on the two values
@@ -518,6 +897,67 @@ impl UncompiledFunction {
         }
     }
     #[inline(always)]
+    /// Make an instruction that loads the element at `index` of the array of `elem_type`
+    /// values starting at `base`, where `base` must be a pointer
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&ctx, &get::<fn(*const u16, i32) -> u16>());
+    /// func.insn_return(func.insn_load_elem(&func[0], &func[1], &get::<u16>()));
+    /// let func = UncompiledFunction::compile(func);
+    /// let values = [10u16, 20, 30];
+    /// assert_eq!(func.try_call::<(*const u16, i32), u16>((values.as_ptr(), 2)), Ok(30));
+    /// ```
+    pub fn insn_load_elem(&self, base: &Val, index: &Val, elem_type: &Ty) -> &Val {
+        if cfg!(debug_assertions) && !base.get_type().is_pointer() {
+            panic!("Value given to insn_load_elem should be pointer, got {:?}", base.get_type());
+        }
+        unsafe {
+            from_ptr(jit_insn_load_elem(
+                self.into(),
+                base.into(),
+                index.into(),
+                elem_type.into()
+            ))
+        }
+    }
+    #[inline(always)]
+    /// Make an instruction that gets the address of the element at `index` of the array
+    /// of `elem_type` values starting at `base`, where `base` must be a pointer
+    pub fn insn_load_elem_address(&self, base: &Val, index: &Val, elem_type: &Ty) -> &Val {
+        if cfg!(debug_assertions) && !base.get_type().is_pointer() {
+            panic!("Value given to insn_load_elem_address should be pointer, got {:?}", base.get_type());
+        }
+        unsafe {
+            from_ptr(jit_insn_load_elem_address(
+                self.into(),
+                base.into(),
+                index.into(),
+                elem_type.into()
+            ))
+        }
+    }
+    #[inline(always)]
+    /// Make an instruction that stores `value` as the element at `index` of the array
+    /// starting at `base`, where `base` must be a pointer to the type of `value`
+    pub fn insn_store_elem(&self, base: &Val, index: &Val, value: &Val) {
+        if cfg!(debug_assertions) && !base.get_type().is_pointer() {
+            panic!("Value given to insn_store_elem should be pointer, got {:?}", base.get_type());
+        }
+        unsafe {
+            jit_insn_store_elem(self.into(), base.into(), index.into(), value.into());
+        }
+    }
+    #[inline(always)]
+    /// Make an instruction that loads `value`, widening it to a full integer if it is
+    /// a small integer type like `u8` or `i16`
+    pub fn insn_load_small(&self, value: &Val) -> &Val {
+        unsafe {
+            from_ptr(jit_insn_load_small(self.into(), value.into()))
+        }
+    }
+    #[inline(always)]
     /// Make an instruction that stores the contents of `val` into `dest`, where `dest` is a
     /// temporary value or local value
     pub fn insn_store(&self, dest: &Val, val: &Val) {
@@ -565,6 +1005,14 @@ impl UncompiledFunction {
         }
     }
     #[inline(always)]
+    /// Make an instruction that branches to a certain label if the program counter
+    /// where the current exception was thrown is not between `start` and `end`
+    pub fn insn_branch_if_pc_not_in_range(&self, start: &Label, end: &Label, label: &mut Label) {
+        unsafe {
+            jit_insn_branch_if_pc_not_in_range(self.into(), **start, **end, &mut **label);
+        }
+    }
+    #[inline(always)]
     /// Make an instruction that branches to a label in the table
     pub fn insn_jump_table(&self, value: &Val, labels: &mut [Label]) {
         unsafe {
@@ -782,28 +1230,18 @@ impl UncompiledFunction {
             flags.bits()
         ))
     }
-    /// Make an instruction that calls a rust closure that has the signature
-    /// given with some arguments
+    /// Make an instruction that calls a rust closure with some arguments
+    ///
+    /// Each argument is passed separately, so `args` must match the types of
+    /// the closure's arguments `A` one by one, which panics otherwise.
     pub fn insn_call_rust<'a, A, R, F>(&'a self, name: Option<&str>,
                         func: &'a F,
-                        args: &[&Val], flags: flags::CallFlags) -> &Val where F:Fn<A, Output = R> + Sized, A:Compile<'a>, R:Compile<'a> {
-        let signature = ::get::<fn(*const (), A) -> R>();
+                        args: &[&Val], flags: flags::CallFlags) -> &Val where F:Fn<A, Output = R> + Sized, A:Args<'a>, R:Compile<'a> {
+        let signature = rust_call_signature::<A, R>();
+        check_call_args(name, &signature, args);
         let func_v = unsafe { mem::transmute::<_, *const ()>(func) }.compile(self);
         let mut args = Vec::from(args);
         args.insert(0, func_v.into());
-        /*
-        if cfg!(debug_assertions) {
-            let num_sig_args = signature.params().count();
-            if args.len() != num_sig_args {
-                panic!("Bad arguments to {:?} - expected {}, got {}", name, num_sig_args, args.len());
-            }
-            for (index, (arg, param)) in args.iter().zip(signature.params()).enumerate().skip(1) {
-                let ty = arg.get_type();
-                if ty != param {
-                    panic!("Bad argument #{} to {:?} - expected {:?}, got {:?}", index, name, param, ty);
-                }
-            }
-        }*/
         unsafe {
             self.insn_call_native(
                 name,
@@ -814,28 +1252,18 @@ impl UncompiledFunction {
             )
         }
     }
-    /// Make an instruction that calls a rust closure that has the signature
-    /// given with some arguments
+    /// Make an instruction that calls a mutable rust closure with some arguments
+    ///
+    /// Each argument is passed separately, so `args` must match the types of
+    /// the closure's arguments `A` one by one, which panics otherwise.
     pub fn insn_call_rust_mut<'a, A, R, F>(&'a self, name: Option<&str>,
                         func: &'a mut F,
-                        args: &[&Val], flags: flags::CallFlags) -> &Val where F:FnMut<A, Output = R> + Sized, A:Compile<'a>, R:Compile<'a> {
-        let signature = ::get::<fn(*const (), A) -> R>();
+                        args: &[&Val], flags: flags::CallFlags) -> &Val where F:FnMut<A, Output = R> + Sized, A:Args<'a>, R:Compile<'a> {
+        let signature = rust_call_signature::<A, R>();
+        check_call_args(name, &signature, args);
         let func_v = unsafe { mem::transmute::<_, *const ()>(func) }.compile(self);
         let mut args = Vec::from(args);
         args.insert(0, func_v.into());
-        /*
-        if cfg!(debug_assertions) {
-            let num_sig_args = signature.params().count();
-            if args.len() != num_sig_args {
-                panic!("Bad arguments to {:?} - expected {}, got {}", name, num_sig_args, args.len());
-            }
-            for (index, (arg, param)) in args.iter().zip(signature.params()).enumerate().skip(1) {
-                let ty = arg.get_type();
-                if ty != param {
-                    panic!("Bad argument #{} to {:?} - expected {:?}, got {:?}", index, name, param, ty);
-                }
-            }
-        }*/
         unsafe {
             self.insn_call_native(
                 name,
@@ -903,6 +1331,20 @@ impl UncompiledFunction {
             from_ptr(f(self.into(), v1.into(), v2.into()))
         }
     }
+    /// Make a binary instruction, or fold it into a constant if both values are
+    /// constants
+    fn insn_folding_binop(&self,
+                    v1: &Val, v2: &Val, op: BinOp,
+                    f: unsafe extern "C" fn(
+                        jit_function_t,
+                        jit_value_t,
+                        jit_value_t) -> jit_value_t)
+                    -> &Val {
+        match fold::fold(self, op, v1, v2) {
+            Some(folded) => folded,
+            None => self.insn_binop(v1, v2, f)
+        }
+    }
     #[inline(always)]
     fn insn_unop(&self,
                     value: &Val,
@@ -982,6 +1424,241 @@ impl UncompiledFunction {
             each();
         })
     }
+    /// Make instructions to run the block `body`, and run `handler` with the
+    /// thrown value if it throws an exception.
+    ///
+    /// The handler only catches exceptions thrown while running `body`, including
+    /// from any functions it calls. If the handler doesn't return or throw, the
+    /// function continues after the try block. Exceptions thrown outside of every
+    /// try block in the function are rethrown to the caller.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let mut ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&mut ctx, &get::<fn(i32) -> i32>());
+    /// func.build_try(|| {
+    ///     func.insn_return(func.insn_div(func.insn_of(12i32), &func[0]));
+    /// }, |_| {
+    ///     func.insn_return(func.insn_of(-1i32));
+    /// });
+    /// let func = UncompiledFunction::compile(func);
+    /// let div = CompiledFunction::to_closure::<i32, i32>(func);
+    /// assert_eq!(div(4), 3);
+    /// assert_eq!(div(0), -1);
+    /// ```
+    pub fn build_try<'a, B, H>(&'a self, body: B, handler: H) where B:FnOnce(), H:FnOnce(&'a Val) {
+        self.insn_uses_catcher();
+        let mut start = Label::new(self);
+        let mut end = Label::new(self);
+        let mut after = Label::new(self);
+        let mut next = Label::new(self);
+        self.insn_label(&mut start);
+        body();
+        self.insn_label(&mut end);
+        self.insn_branch(&mut after);
+        let exception = self.start_dispatch();
+        self.insn_branch_if_pc_not_in_range(&start, &end, &mut next);
+        handler(exception);
+        self.insn_branch(&mut after);
+        self.set_next_dispatch(&next);
+        self.insn_label(&mut after);
+    }
+    /// Make instructions to run the block `body`, then run `finally`.
+    ///
+    /// The finally block is run when the body finishes normally, when it returns
+    /// from the function using `insn_return` or `insn_default_return`, and when
+    /// it throws an exception, in which case the exception is rethrown once the
+    /// finally block has finished.
+    ///
+    /// Branching out of the body with `insn_branch` or the like to a label
+    /// outside it skips the finally block, since there's no telling where a
+    /// label will be placed when the branch is made. Branch to the end of the
+    /// body instead, and carry on from after this.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// use std::cell::Cell;
+    /// let mut ctx = Context::<()>::new();
+    /// let cleanups = Cell::new(0);
+    /// let cleanup = |_: i32| cleanups.set(cleanups.get() + 1);
+    /// let func = UncompiledFunction::new(&mut ctx, &get::<fn(i32) -> i32>());
+    /// func.build_try_finally(|| {
+    ///     func.build_if(func.insn_eq(&func[0], func.insn_of(0i32)), || {
+    ///         func.insn_return(func.insn_of(-1i32));
+    ///     });
+    /// }, || {
+    ///     func.insn_call_rust(Some("cleanup"), &cleanup, &[&func[0]], flags::NO_THROW);
+    /// });
+    /// func.insn_return(&func[0]);
+    /// let func = UncompiledFunction::compile(func);
+    /// let func = CompiledFunction::to_closure::<i32, i32>(func);
+    /// assert_eq!(func(0), -1);
+    /// assert_eq!(func(5), 5);
+    /// assert_eq!(cleanups.get(), 2);
+    /// ```
+    pub fn build_try_finally<B, F>(&self, body: B, finally: F) where B:FnOnce(), F:FnOnce() {
+        self.insn_uses_catcher();
+        let mut start = Label::new(self);
+        let mut end = Label::new(self);
+        let mut after = Label::new(self);
+        let mut next = Label::new(self);
+        let mut finally_label = Label::new(self);
+        self.insn_label(&mut start);
+        self.push_finally_block(*finally_label);
+        body();
+        self.finally_blocks().unwrap().pop();
+        self.insn_label(&mut end);
+        unsafe {
+            jit_insn_call_finally(self.into(), &mut *finally_label);
+        }
+        self.insn_branch(&mut after);
+        self.start_dispatch();
+        self.insn_branch_if_pc_not_in_range(&start, &end, &mut next);
+        unsafe {
+            jit_insn_call_finally(self.into(), &mut *finally_label);
+        }
+        self.insn_branch(&mut next);
+        self.set_next_dispatch(&next);
+        unsafe {
+            jit_insn_start_finally(self.into(), &mut *finally_label);
+            finally();
+            jit_insn_return_from_finally(self.into());
+        }
+        self.insn_label(&mut after);
+    }
+    /// Make instructions to run the block `body`, and run `handler` with the
+    /// thrown value if it throws an exception that `filter` accepts.
+    ///
+    /// The filter is given the thrown value and should give a value that is
+    /// true when the exception should be handled by `handler`. Exceptions it
+    /// rejects carry on to the try blocks around this one, or to the caller.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let mut ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&mut ctx, &get::<fn(isize) -> isize>());
+    /// func.build_try(|| {
+    ///     func.build_try_filter(|| {
+    ///         func.insn_throw(&func[0]);
+    ///     }, |exc| {
+    ///         func.insn_lt(exc, func.insn_of(10isize))
+    ///     }, |_| {
+    ///         func.insn_return(func.insn_of(1isize));
+    ///     });
+    /// }, |_| {
+    ///     func.insn_return(func.insn_of(2isize));
+    /// });
+    /// func.insn_default_return();
+    /// let func = UncompiledFunction::compile(func);
+    /// let func = CompiledFunction::to_closure::<isize, isize>(func);
+    /// assert_eq!(func(5), 1);
+    /// assert_eq!(func(50), 2);
+    /// ```
+    pub fn build_try_filter<'a, B, F, H>(&'a self, body: B, filter: F, handler: H)
+        where B:FnOnce(), F:FnOnce(&'a Val) -> &'a Val, H:FnOnce(&'a Val) {
+        self.insn_uses_catcher();
+        let mut start = Label::new(self);
+        let mut end = Label::new(self);
+        let mut after = Label::new(self);
+        let mut next = Label::new(self);
+        let mut filter_label = Label::new(self);
+        let bool_t = ::get::<bool>();
+        self.insn_label(&mut start);
+        body();
+        self.insn_label(&mut end);
+        self.insn_branch(&mut after);
+        let exception = self.start_dispatch();
+        self.insn_branch_if_pc_not_in_range(&start, &end, &mut next);
+        let accepted: &Val = unsafe {
+            from_ptr(jit_insn_call_filter(self.into(), &mut *filter_label, exception.into(), (&*bool_t).into()))
+        };
+        self.insn_branch_if_not(accepted, &mut next);
+        handler(exception);
+        self.insn_branch(&mut after);
+        self.set_next_dispatch(&next);
+        unsafe {
+            let value = from_ptr(jit_insn_start_filter(self.into(), &mut *filter_label, exception.get_type().into()));
+            let accepted = self.insn_convert(filter(value), &bool_t, false);
+            jit_insn_return_from_filter(self.into(), accepted.into());
+        }
+        self.insn_label(&mut after);
+    }
+    /// Get the labels of the finally blocks around the code being built, with
+    /// the innermost last, if any have been built in this function
+    fn finally_blocks(&self) -> Option<&mut Vec<jit_label_t>> {
+        unsafe {
+            let blocks = jit_function_get_meta(self.into(), FINALLY_BLOCKS) as *mut Vec<jit_label_t>;
+            blocks.as_mut()
+        }
+    }
+    /// Add the label of a finally block around the code about to be built,
+    /// keeping track of them from the first one built in this function
+    fn push_finally_block(&self, label: jit_label_t) {
+        if let Some(blocks) = self.finally_blocks() {
+            return blocks.push(label)
+        }
+        unsafe {
+            let boxed: Box<Vec<jit_label_t>> = Box::new(vec![label]);
+            if jit_function_set_meta(self.into(), FINALLY_BLOCKS, mem::transmute(boxed), Some(::free_data::<Vec<jit_label_t>>), 1) == 0 {
+                oom()
+            }
+        }
+    }
+    /// Check if the code being built is inside the body of a `build_try_finally`
+    fn in_finally_body(&self) -> bool {
+        self.finally_blocks().map(|blocks| !blocks.is_empty()).unwrap_or(false)
+    }
+    /// Make instructions to run the finally blocks around the code being built,
+    /// from the innermost outwards
+    fn insn_call_finally_blocks(&self) {
+        let mut blocks = match self.finally_blocks() {
+            Some(blocks) => blocks.clone(),
+            None => return
+        };
+        for label in blocks.iter_mut().rev() {
+            unsafe {
+                jit_insn_call_finally(self.into(), label);
+            }
+        }
+    }
+    /// Start the next block in the chain of blocks that dispatch exceptions to
+    /// their try blocks, and get the thrown exception.
+    ///
+    /// LibJIT only allows a single catcher block per function, so the first
+    /// try block makes the catcher, and each try block after that continues
+    /// from the label the previous one left in the function's metadata.
+    fn start_dispatch(&self) -> &Val {
+        unsafe {
+            let next = jit_function_get_meta(self.into(), CATCH_DISPATCH) as *mut jit_label_t;
+            if next.is_null() {
+                from_ptr(jit_insn_start_catcher(self.into()))
+            } else {
+                jit_insn_label(self.into(), next);
+                self.insn_thrown_exception()
+            }
+        }
+    }
+    /// Set the label the next try block should continue dispatching exceptions from
+    fn set_next_dispatch(&self, label: &Label) {
+        unsafe {
+            let boxed = Box::new(**label);
+            if jit_function_set_meta(self.into(), CATCH_DISPATCH, mem::transmute(boxed), Some(::free_data::<jit_label_t>), 1) == 0 {
+                oom()
+            }
+        }
+    }
+    /// End the chain of exception dispatch blocks by rethrowing anything
+    /// that no try block caught
+    fn end_dispatch(&self) {
+        unsafe {
+            let next = jit_function_get_meta(self.into(), CATCH_DISPATCH) as *mut jit_label_t;
+            if !next.is_null() {
+                jit_insn_label(self.into(), next);
+                jit_insn_rethrow_unhandled(self.into());
+                jit_function_free_meta(self.into(), CATCH_DISPATCH);
+            }
+        }
+    }
     #[inline(always)]
     /// Set the optimization level of the function, where the bigger the level,
     /// the more effort should be spent optimising
@@ -1019,6 +1696,7 @@ impl UncompiledFunction {
     #[inline(always)]
     /// Compile the function
     pub fn compile<'a>(func: CSemiBox<'a, UncompiledFunction>) -> CSemiBox<'a, CompiledFunction> {
+        func.end_dispatch();
         unsafe {
             let ptr = (&*func).into();
             mem::forget(func);
@@ -1028,6 +1706,78 @@ impl UncompiledFunction {
     }
 }
 
+/// Get the signature LibJIT should call a closure taking the arguments `A` and
+/// returning `R` with, which is a pointer to the closure followed by each of
+/// the arguments, since the "rust-call" ABI passes them separately
+fn rust_call_signature<'a, A, R>() -> Type where A:Args<'a>, R:Compile<'a> {
+    let closure = ::get::<*const ()>();
+    let types = A::get_types();
+    let mut params: Vec<&Ty> = Some(&*closure).into_iter().chain(types.iter().map(|ty| &**ty)).collect();
+    Type::new_signature(Abi::CDecl, &R::get_type(), &mut params)
+}
+
+/// Panic if `args` don't match the parameters of `signature` after the
+/// closure pointer
+fn check_call_args(name: Option<&str>, signature: &Ty, args: &[&Val]) {
+    let num_args = signature.params().count() - 1;
+    if args.len() != num_args {
+        panic!("Bad arguments to {:?} - expected {}, got {}", name, num_args, args.len());
+    }
+    for (index, (arg, param)) in args.iter().zip(signature.params().skip(1)).enumerate() {
+        let ty = arg.get_type();
+        if ty != param {
+            panic!("Bad argument #{} to {:?} - expected {:?}, got {:?}", index, name, param, ty);
+        }
+    }
+}
+
+/// Build the body of an on-demand function the first time it is called
+///
+/// If the builder panics, this reports a compile error so the call throws.
+extern fn build_on_demand(func: jit_function_t) -> c_int {
+    unsafe {
+        let builder = jit_function_get_meta(func, ON_DEMAND) as *mut Box<FnMut(&UncompiledFunction)>;
+        if builder.is_null() {
+            return BuiltinException::CompileError as c_int
+        }
+        let func: &UncompiledFunction = from_ptr(func);
+        let built = panic::catch_unwind(AssertUnwindSafe(|| {
+            (*builder)(func);
+            func.end_dispatch();
+        }));
+        jit_function_free_meta(func.into(), ON_DEMAND);
+        match built {
+            Ok(()) => 1,
+            Err(_) => BuiltinException::CompileError as c_int
+        }
+    }
+}
+
+/// Count a call to a tiered function, and once it has been called enough times
+/// build its body again at the max optimization level and swap it in
+extern fn count_call(func: jit_function_t) {
+    unsafe {
+        let tiering = jit_function_get_meta(func, TIERING) as *const Tiering;
+        if tiering.is_null() {
+            return
+        }
+        let tiering = &*tiering;
+        // only the first call to reach the threshold promotes it, even if
+        // several threads are running the unoptimized body at once
+        let calls = tiering.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if calls < tiering.threshold || tiering.promoted.swap(true, Ordering::SeqCst) {
+            return
+        }
+        jit_function_set_optimization_level(func, UncompiledFunction::get_max_optimization_level());
+        let func: &Func = from_ptr(func);
+        // if this fails or the builder panics, which the panic hook has already
+        // reported, the unoptimized body keeps running, which is still correct
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            func.rebuild(|func| (tiering.builder)(func))
+        }));
+    }
+}
+
 /// To be implemented by any type that is a member of a function
 pub trait FunctionMember {
     /// Get the function containing this value.
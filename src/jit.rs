@@ -55,12 +55,21 @@ extern crate libjit_sys as raw;
 use raw::*;
 use std::os::raw::c_void;
 use std::mem;
+pub use closure::{NativeClosure, ClosureArgs, ClosureReturn};
 pub use compile::Compile;
-pub use context::{Context, ContextMember};
+pub use context::{Context, ContextMember, Location};
+pub use debugger::{marks, Debugger, Breakpoint, BreakpointId, Event, EventKind, ThreadId};
+pub use dynamic::{ApplyError, DynamicCall, JitValue};
 pub use elf::*;
+pub use exception::{BuiltinException, JitException};
+pub use from_jit::{FromJit, FromJitError};
 pub use function::{flags, Abi, UncompiledFunction, Func, CompiledFunction};
 pub use function::flags::CallFlags;
 pub use label::Label;
+pub use memory::{page_size, round_up_to_page, round_down_to_page};
+pub use memory::{MemoryManager, VmemMemoryManager, Protection, RegionKind, VirtualRegion};
+pub use trace::{StackTrace, Frame, Frames};
+pub use typed::{Args, TypedFunction};
 pub use insn::{Block, Instruction, InstructionIter};
 pub use types::kind::TypeKind;
 pub use types::{kind, get, Type, Field, Fields, Params, CowType, StaticType, Ty, TaggedType};
@@ -96,6 +105,13 @@ pub fn supports_threads() -> bool {
         jit_supports_threads() != 0
     }
 }
+/// Check if the JIT supports closures
+#[inline]
+pub fn supports_closures() -> bool {
+    unsafe {
+        jit_supports_closures() != 0
+    }
+}
 /// Check if the JIT supports virtual memory
 #[inline]
 pub fn supports_virtual_memory() -> bool {
@@ -106,11 +122,20 @@ pub fn supports_virtual_memory() -> bool {
 #[macro_use]
 mod macros;
 mod context;
+mod closure;
 mod compile;
+mod debugger;
+mod dynamic;
 mod elf;
+mod exception;
+mod fold;
+mod from_jit;
 mod function;
 mod insn;
 mod label;
+mod memory;
+mod trace;
+mod typed;
 mod types;
 mod util;
 mod value;
@@ -38,7 +38,7 @@ macro_rules! compile_ptr(
     })
 );
 macro_rules! compile_func(
-    (fn($($arg:ident),*) -> $ret:ty, $sig:ty, $ext_sig:ty) => (
+    (fn($($arg:ident),*) -> $ret:ty, $sig:ty) => (
         impl<'a, $($arg:Compile<'a>,)* R:Compile<'a>> Compile<'a> for $sig {
             #[inline(always)]
             fn compile(self, func:&'a UncompiledFunction) -> &'a Val {
@@ -49,18 +49,16 @@ macro_rules! compile_func(
                 Type::new_signature(CDecl, &get::<R>(), &mut [$(&get::<$arg>()),*]).into()
             }
         }
-        impl<'a, $($arg:Compile<'a>,)* R:Compile<'a>> Compile<'a> for $ext_sig {
-            #[inline(always)]
-            fn compile(self, func:&'a UncompiledFunction) -> &'a Val {
-                func.insn_convert(compile_ptr!(func, self, &*Self::get_type()), &Self::get_type(), false)
-            }
-            #[inline(always)]
-            fn get_type() -> CowType<'a> {
-                Type::new_signature(CDecl, &get::<R>(), &mut [$(&get::<$arg>()),*]).into()
-            }
-        }
     )
 );
+macro_rules! compile_funcs(
+    ($(($($arg:ident),*)),+) => ($(
+        compile_func!(fn($($arg),*) -> R, fn($($arg),*) -> R);
+        compile_func!(fn($($arg),*) -> R, extern fn($($arg),*) -> R);
+        compile_func!(fn($($arg),*) -> R, unsafe fn($($arg),*) -> R);
+        compile_func!(fn($($arg),*) -> R, unsafe extern fn($($arg),*) -> R);
+    )+)
+);
 macro_rules! compile_tuple(
     ($($ty:ident),+ => $($name:ident),+) => (
         impl<'a, $($ty),+> Compile<'a> for ($($ty),+) where $($ty:Compile<'a>),+ {
@@ -263,3 +261,163 @@ macro_rules! builtin_types(
         $(builtin_type!($c_name -> $rust_name);)+
     )
 );
+macro_rules! typed_function(
+    (($($arg:ident),*) => ($($name:ident),*)) => (
+        impl<'a $(, $arg:Compile<'a>)*> Args<'a> for ($($arg,)*) {
+            fn get_types() -> Vec<CowType<'a>> {
+                vec![$(::get::<$arg>()),*]
+            }
+            fn with_ptrs<F, T>(self, cb: F) -> T where F:FnOnce(&mut [*mut c_void]) -> T {
+                let ($(mut $name,)*) = self;
+                cb(&mut [$(&mut $name as *mut $arg as *mut c_void),*])
+            }
+        }
+        impl<'a, $($arg,)* R> FnOnce<($($arg,)*)> for TypedFunction<'a, ($($arg,)*), R> {
+            type Output = R;
+            extern "rust-call" fn call_once(self, args: ($($arg,)*)) -> R {
+                Fn::call(&self, args)
+            }
+        }
+        impl<'a, $($arg,)* R> FnMut<($($arg,)*)> for TypedFunction<'a, ($($arg,)*), R> {
+            extern "rust-call" fn call_mut(&mut self, args: ($($arg,)*)) -> R {
+                Fn::call(&*self, args)
+            }
+        }
+        impl<'a, $($arg,)* R> Fn<($($arg,)*)> for TypedFunction<'a, ($($arg,)*), R> {
+            extern "rust-call" fn call(&self, ($($name,)*): ($($arg,)*)) -> R {
+                unsafe {
+                    let func: extern fn($($arg),*) -> R = mem::transmute(self.ptr);
+                    func($($name),*)
+                }
+            }
+        }
+    )
+);
+macro_rules! typed_functions(
+    ($(($($arg:ident),*) => ($($name:ident),*));+) => (
+        $(typed_function!(($($arg),*) => ($($name),*));)+
+    )
+);
+macro_rules! compile_arrays(
+    ($($len:expr),+) => ($(
+        impl<'a, T> Compile<'a> for [T; $len] where T:Compile<'a> {
+            #[inline(always)]
+            fn compile(self, func:&'a UncompiledFunction) -> &'a Val {
+                use std::mem::ManuallyDrop;
+                use std::ptr;
+                let ty = Self::get_type();
+                let array = Val::new(func, &ty);
+                array.set_addressable();
+                // each element is moved out once, so if compiling one panics
+                // the rest are leaked rather than dropped twice
+                let elems = ManuallyDrop::new(self);
+                for index in 0..$len {
+                    let elem = unsafe { ptr::read(&elems[index]) };
+                    array.set_elem(func.insn_of(index), func.insn_of(elem));
+                }
+                array
+            }
+            #[inline(always)]
+            fn get_type() -> CowType<'a> {
+                use std::mem;
+                let mut ty = Type::new_array(&get::<T>(), $len);
+                ty.set_size_and_alignment(mem::size_of::<Self>(), mem::align_of::<Self>());
+                ty.into()
+            }
+        }
+    )+)
+);
+macro_rules! from_jit_prims(
+    ($($ty:ty => $kind:ident),+) => ($(
+        impl FromJit for $ty {
+            #[inline(always)]
+            fn is_type(ty: &Ty) -> bool {
+                ty.remove_tags().get_kind() == kind::$kind
+            }
+            #[inline(always)]
+            unsafe fn read_unchecked(_: &Ty, ptr: *const c_void) -> $ty {
+                ptr::read_unaligned(ptr as *const $ty)
+            }
+        }
+    )+)
+);
+macro_rules! from_jit_tuple(
+    ($($arg:ident),+) => (
+        impl<$($arg),+> FromJit for ($($arg,)+) where $($arg:FromJit),+ {
+            fn is_type(ty: &Ty) -> bool {
+                let mut fields = ty.fields();
+                ty.is_struct() && fields.size_hint().0 == [$(stringify!($arg)),+].len()
+                    $(&& $arg::is_type(fields.next().unwrap().get_type()))+
+            }
+            unsafe fn read_unchecked(ty: &Ty, ptr: *const c_void) -> ($($arg,)+) {
+                let mut fields = ty.fields();
+                ($({
+                    let field = fields.next().unwrap();
+                    $arg::read_unchecked(field.get_type(), offset(ptr, field.get_offset()))
+                },)+)
+            }
+        }
+    )
+);
+macro_rules! from_jit_arrays(
+    ($($len:expr),+) => ($(
+        impl<T> FromJit for [T; $len] where T:FromJit {
+            fn is_type(ty: &Ty) -> bool {
+                match (ty.get_elem_type(), ty.get_array_len()) {
+                    (Some(elem), Some(len)) => len == $len && T::is_type(elem),
+                    _ => $len == 0 && ty.is_struct() && ty.fields().next().is_none()
+                }
+            }
+            unsafe fn read_unchecked(ty: &Ty, ptr: *const c_void) -> [T; $len] {
+                // the elements are read into a vector first, so the ones read
+                // so far are dropped properly if reading one panics
+                let mut elems: Vec<T> = Vec::with_capacity($len);
+                if let Some(elem) = ty.get_elem_type() {
+                    for field in ty.fields() {
+                        elems.push(T::read_unchecked(elem, offset(ptr, field.get_offset())));
+                    }
+                }
+                if elems.len() != $len {
+                    panic!("{:?} has {} elements, not {}", ty, elems.len(), $len)
+                }
+                let array = ptr::read(elems.as_ptr() as *const [T; $len]);
+                elems.set_len(0);
+                array
+            }
+        }
+    )+)
+);
+macro_rules! fold_ints(
+    ($($ty:ident),+) => ($(
+        impl Fold for $ty {
+            fn fold(op: BinOp, a: $ty, b: $ty) -> Option<$ty> {
+                match op {
+                    BinOp::Add => Some(a.wrapping_add(b)),
+                    BinOp::Sub => Some(a.wrapping_sub(b)),
+                    BinOp::Mul => Some(a.wrapping_mul(b)),
+                    BinOp::Div => a.checked_div(b),
+                    BinOp::Rem => a.checked_rem(b),
+                    BinOp::And => Some(a & b),
+                    BinOp::Or => Some(a | b),
+                    BinOp::Xor => Some(a ^ b)
+                }
+            }
+        }
+    )+)
+);
+macro_rules! fold_floats(
+    ($($ty:ident),+) => ($(
+        impl Fold for $ty {
+            fn fold(op: BinOp, a: $ty, b: $ty) -> Option<$ty> {
+                match op {
+                    BinOp::Add => Some(a + b),
+                    BinOp::Sub => Some(a - b),
+                    BinOp::Mul => Some(a * b),
+                    BinOp::Div => Some(a / b),
+                    BinOp::Rem => Some(a % b),
+                    BinOp::And | BinOp::Or | BinOp::Xor => None
+                }
+            }
+        }
+    )+)
+);
@@ -0,0 +1,476 @@
+use raw::*;
+use std::os::raw::{c_int, c_void};
+use std::{cmp, mem, ptr};
+/// The context metadata key holding the memory manager waiting to be used by the context
+pub const MEMORY_MANAGER: i32 = -3;
+/// The memory was allocated successfully
+const MEMORY_OK: c_int = 0;
+/// The limit must be extended before the function is compiled again
+const MEMORY_RESTART: c_int = 1;
+/// The function is too big to fit in memory
+const MEMORY_TOO_BIG: c_int = 2;
+/// How many pages a code or data region takes up at least
+const REGION_PAGES: usize = 16;
+/// How code is aligned at the start of each function
+const FUNCTION_ALIGNMENT: usize = 32;
+/// What a region of memory can be used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protection {
+    /// The memory can't be accessed
+    None,
+    /// The memory can be read
+    Read,
+    /// The memory can be read and written
+    ReadWrite,
+    /// The memory can be read and executed
+    ExecRead,
+    /// The memory can be read, written and executed
+    ExecReadWrite
+}
+impl Protection {
+    /// Get the LibJIT protection mode for this
+    pub fn to_raw(self) -> jit_prot_t {
+        match self {
+            Protection::None => JIT_PROT_NONE,
+            Protection::Read => JIT_PROT_READ,
+            Protection::ReadWrite => JIT_PROT_READ_WRITE,
+            Protection::ExecRead => JIT_PROT_EXEC_READ,
+            Protection::ExecReadWrite => JIT_PROT_EXEC_READ_WRITE
+        }
+    }
+}
+/// Get the size of a page of virtual memory
+pub fn page_size() -> usize {
+    unsafe {
+        jit_vmem_init();
+        jit_vmem_page_size() as usize
+    }
+}
+/// Round `size` up to a whole number of pages
+pub fn round_up_to_page(size: usize) -> usize {
+    unsafe {
+        jit_vmem_init();
+        jit_vmem_round_up(size as jit_nuint) as usize
+    }
+}
+/// Round `size` down to a whole number of pages
+pub fn round_down_to_page(size: usize) -> usize {
+    unsafe {
+        jit_vmem_init();
+        jit_vmem_round_down(size as jit_nuint) as usize
+    }
+}
+/// A region of virtual memory, which is released when this is dropped
+///
+/// Address space is reserved for the whole region up front, and then pages of
+/// it are committed when they are needed, so pages which are never committed or
+/// are made inaccessible with `protect` act as guard pages.
+///
+/// ```rust
+/// use jit::*;
+/// let page = page_size();
+/// let region = VirtualRegion::reserve(page * 3).unwrap();
+/// // leave the first and last pages as guard pages
+/// assert!(region.commit(page, page, Protection::ReadWrite));
+/// unsafe {
+///     *region.as_ptr().offset(page as isize) = 42;
+/// }
+/// ```
+pub struct VirtualRegion {
+    start: *mut u8,
+    size: usize
+}
+impl VirtualRegion {
+    /// Reserve address space for a region of at least `size` bytes, without
+    /// committing any of it
+    ///
+    /// This gives `None` if the space couldn't be reserved, or if the region
+    /// would be bigger than the 4GiB LibJIT can reserve at once.
+    pub fn reserve(size: usize) -> Option<VirtualRegion> {
+        let size = match VirtualRegion::reservable(size) {
+            Some(size) => size,
+            None => return None
+        };
+        unsafe {
+            VirtualRegion::from_raw(jit_vmem_reserve(size) as *mut u8, size as usize)
+        }
+    }
+    /// Reserve a region of at least `size` bytes and commit all of it with the
+    /// protection given
+    ///
+    /// This gives `None` in the same cases as `reserve`.
+    pub fn reserve_committed(size: usize, prot: Protection) -> Option<VirtualRegion> {
+        let size = match VirtualRegion::reservable(size) {
+            Some(size) => size,
+            None => return None
+        };
+        unsafe {
+            VirtualRegion::from_raw(jit_vmem_reserve_committed(size, prot.to_raw()) as *mut u8, size as usize)
+        }
+    }
+    /// Round `size` up to a whole number of pages, checking it fits in the
+    /// size LibJIT takes when reserving memory
+    fn reservable(size: usize) -> Option<jit_uint> {
+        if size.checked_add(page_size() - 1).is_none() {
+            return None
+        }
+        let size = round_up_to_page(size);
+        if size > jit_uint::max_value() as usize {
+            None
+        } else {
+            Some(size as jit_uint)
+        }
+    }
+    unsafe fn from_raw(start: *mut u8, size: usize) -> Option<VirtualRegion> {
+        if start.is_null() {
+            None
+        } else {
+            Some(VirtualRegion {
+                start: start,
+                size: size
+            })
+        }
+    }
+    /// Get a pointer to the start of the region
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.start
+    }
+    /// Get the size of the region in bytes
+    pub fn len(&self) -> usize {
+        self.size
+    }
+    /// Get the start of the pages covering `size` bytes from `offset`, and how
+    /// many bytes they cover
+    fn pages(&self, offset: usize, size: usize) -> (*mut c_void, jit_uint) {
+        let end = offset.checked_add(size).expect("Virtual memory range overflowed");
+        if end > self.size {
+            panic!("Range {}..{} is outside the virtual memory region of size {}", offset, end, self.size)
+        }
+        let start = round_down_to_page(offset);
+        let end = round_up_to_page(end);
+        unsafe {
+            (self.start.offset(start as isize) as *mut c_void, (end - start) as jit_uint)
+        }
+    }
+    /// Commit the pages covering `size` bytes from `offset` with the protection
+    /// given, giving `true` if this succeeded
+    ///
+    /// This panics if the range is outside the region.
+    pub fn commit(&self, offset: usize, size: usize, prot: Protection) -> bool {
+        let (ptr, size) = self.pages(offset, size);
+        unsafe {
+            jit_vmem_commit(ptr, size, prot.to_raw()) != 0
+        }
+    }
+    /// Decommit the pages covering `size` bytes from `offset`, giving their memory
+    /// back but keeping their address space reserved
+    ///
+    /// This panics if the range is outside the region.
+    pub fn decommit(&self, offset: usize, size: usize) -> bool {
+        let (ptr, size) = self.pages(offset, size);
+        unsafe {
+            jit_vmem_decommit(ptr, size) != 0
+        }
+    }
+    /// Change the protection of the pages covering `size` bytes from `offset`,
+    /// giving `true` if this succeeded
+    ///
+    /// This panics if the range is outside the region.
+    pub fn protect(&self, offset: usize, size: usize, prot: Protection) -> bool {
+        let (ptr, size) = self.pages(offset, size);
+        unsafe {
+            jit_vmem_protect(ptr, size, prot.to_raw()) != 0
+        }
+    }
+}
+impl Drop for VirtualRegion {
+    fn drop(&mut self) {
+        unsafe {
+            jit_vmem_release(self.start as *mut c_void, self.size as jit_uint);
+        }
+    }
+}
+/// What a region of memory is allocated for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Machine code generated for functions, which must be executable
+    Code,
+    /// Data used by generated code, like constants
+    Data
+}
+/// Allocates the memory a context puts its generated code and data in
+///
+/// Regions are always a whole number of pages long, and are carved up into
+/// functions and data by the context. Returning null from `alloc` refuses the
+/// allocation, which makes compiling the function that needed it fail, so this
+/// can be used to account for and cap the memory used by a context.
+///
+/// Code regions must be readable, writable and executable, and stay that way
+/// until they are freed, since LibJIT can patch code it has already emitted.
+pub trait MemoryManager {
+    /// Allocate a region of `size` bytes for `kind`, or give null to refuse it
+    fn alloc(&mut self, size: usize, kind: RegionKind) -> *mut u8;
+    /// Free a region given by `alloc`
+    fn free(&mut self, ptr: *mut u8, size: usize, kind: RegionKind);
+}
+/// The default memory manager, which allocates straight from virtual memory
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VmemMemoryManager;
+impl VmemMemoryManager {
+    /// Create a new virtual memory manager
+    pub fn new() -> VmemMemoryManager {
+        unsafe {
+            jit_vmem_init();
+        }
+        VmemMemoryManager
+    }
+}
+impl MemoryManager for VmemMemoryManager {
+    fn alloc(&mut self, size: usize, kind: RegionKind) -> *mut u8 {
+        let prot = match kind {
+            RegionKind::Code => Protection::ExecReadWrite,
+            RegionKind::Data => Protection::ReadWrite
+        };
+        unsafe {
+            jit_vmem_reserve_committed(size as jit_uint, prot.to_raw()) as *mut u8
+        }
+    }
+    fn free(&mut self, ptr: *mut u8, size: usize, _kind: RegionKind) {
+        unsafe {
+            jit_vmem_release(ptr as *mut c_void, size as jit_uint);
+        }
+    }
+}
+/// A region of memory given by a memory manager
+struct Region {
+    start: *mut u8,
+    size: usize,
+    kind: RegionKind
+}
+/// Where a compiled function's code is
+struct FunctionInfo {
+    func: jit_function_t,
+    start: *mut u8,
+    end: *mut u8
+}
+/// The state of a context's memory, laid out in regions given by its manager
+struct Cache {
+    manager: Box<MemoryManager>,
+    /// LibJIT's own memory context, used for function objects, trampolines
+    /// and closures, which LibJIT lays out itself
+    inner: jit_memory_context_t,
+    regions: Vec<Region>,
+    functions: Vec<Box<FunctionInfo>>,
+    /// The region code is being written to and how much of it is used
+    code: Option<(*mut u8, *mut u8, *mut u8)>,
+    /// The region data is being allocated from and how much of it is used
+    data: Option<(*mut u8, *mut u8, *mut u8)>,
+    /// The function being compiled and where its code starts
+    current: Option<(jit_function_t, *mut u8)>
+}
+impl Cache {
+    /// Allocate a region of at least `size` bytes, giving its start and end
+    fn alloc(&mut self, size: usize, kind: RegionKind) -> Option<(*mut u8, *mut u8)> {
+        let size = unsafe {
+            let min_size = jit_vmem_page_size() as usize * REGION_PAGES;
+            jit_vmem_round_up(cmp::max(size, min_size) as jit_nuint) as usize
+        };
+        let start = self.manager.alloc(size, kind);
+        if start.is_null() {
+            None
+        } else {
+            self.regions.push(Region {
+                start: start,
+                size: size,
+                kind: kind
+            });
+            Some((start, unsafe { start.offset(size as isize) }))
+        }
+    }
+}
+impl Drop for Cache {
+    fn drop(&mut self) {
+        for region in self.regions.drain(..) {
+            self.manager.free(region.start, region.size, region.kind);
+        }
+        inner().destroy.unwrap()(self.inner);
+    }
+}
+/// Round `ptr` up to a multiple of `align`
+fn align_up(ptr: *mut u8, align: usize) -> *mut u8 {
+    ((ptr as usize + align - 1) & !(align - 1)) as *mut u8
+}
+/// Get the cache from the memory context LibJIT gives
+fn cache<'a>(memctx: jit_memory_context_t) -> &'a mut Cache {
+    unsafe {
+        &mut *(memctx as *mut Cache)
+    }
+}
+/// Get LibJIT's own memory manager
+fn inner() -> &'static Struct_jit_memory_manager {
+    unsafe {
+        &*jit_default_memory_manager()
+    }
+}
+extern fn create(context: jit_context_t) -> jit_memory_context_t {
+    unsafe {
+        let manager = jit_context_get_meta(context, MEMORY_MANAGER) as *mut Option<Box<MemoryManager>>;
+        let manager = match manager.as_mut().and_then(|manager| manager.take()) {
+            Some(manager) => manager,
+            None => Box::new(VmemMemoryManager::new())
+        };
+        jit_context_free_meta(context, MEMORY_MANAGER);
+        let cache = Box::new(Cache {
+            manager: manager,
+            inner: inner().create.unwrap()(context),
+            regions: Vec::new(),
+            functions: Vec::new(),
+            code: None,
+            data: None,
+            current: None
+        });
+        mem::transmute(cache)
+    }
+}
+extern fn destroy(memctx: jit_memory_context_t) {
+    unsafe {
+        let cache: Box<Cache> = mem::transmute(memctx);
+        mem::drop(cache);
+    }
+}
+extern fn find_function_info(memctx: jit_memory_context_t, pc: *mut c_void) -> jit_function_info_t {
+    let pc = pc as *mut u8;
+    cache(memctx).functions.iter()
+        .find(|info| info.start <= pc && pc < info.end)
+        .map(|info| &**info as *const FunctionInfo as jit_function_info_t)
+        .unwrap_or(ptr::null_mut())
+}
+extern fn get_function(_memctx: jit_memory_context_t, info: jit_function_info_t) -> jit_function_t {
+    unsafe { (*(info as *const FunctionInfo)).func }
+}
+extern fn get_function_start(_memctx: jit_memory_context_t, info: jit_function_info_t) -> *mut c_void {
+    unsafe { (*(info as *const FunctionInfo)).start as *mut c_void }
+}
+extern fn get_function_end(_memctx: jit_memory_context_t, info: jit_function_info_t) -> *mut c_void {
+    unsafe { (*(info as *const FunctionInfo)).end as *mut c_void }
+}
+extern fn alloc_function(memctx: jit_memory_context_t) -> jit_function_t {
+    inner().alloc_function.unwrap()(cache(memctx).inner)
+}
+extern fn free_function(memctx: jit_memory_context_t, func: jit_function_t) {
+    let cache = cache(memctx);
+    cache.functions.retain(|info| info.func != func);
+    inner().free_function.unwrap()(cache.inner, func)
+}
+extern fn start_function(memctx: jit_memory_context_t, func: jit_function_t) -> c_int {
+    let cache = cache(memctx);
+    if cache.code.is_none() {
+        match cache.alloc(0, RegionKind::Code) {
+            Some((start, limit)) => cache.code = Some((start, start, limit)),
+            None => return MEMORY_TOO_BIG
+        }
+    }
+    let (start, brk, limit) = cache.code.unwrap();
+    let brk = align_up(brk, FUNCTION_ALIGNMENT);
+    if brk >= limit {
+        return MEMORY_RESTART
+    }
+    cache.code = Some((start, brk, limit));
+    cache.current = Some((func, brk));
+    MEMORY_OK
+}
+extern fn end_function(memctx: jit_memory_context_t, result: c_int) -> c_int {
+    let cache = cache(memctx);
+    if let Some((func, func_start)) = cache.current.take() {
+        let (start, brk, limit) = cache.code.unwrap();
+        if result == MEMORY_OK {
+            cache.functions.push(Box::new(FunctionInfo {
+                func: func,
+                start: func_start,
+                end: brk
+            }));
+        } else {
+            cache.code = Some((start, func_start, limit));
+        }
+    }
+    MEMORY_OK
+}
+extern fn extend_limit(memctx: jit_memory_context_t, count: c_int) -> c_int {
+    let cache = cache(memctx);
+    let size = cache.code.map(|(start, _, limit)| limit as usize - start as usize).unwrap_or(0);
+    let size = size << cmp::min(cmp::max(count, 1), 8);
+    // the old region is left writable, since LibJIT can still patch code in it
+    match cache.alloc(size, RegionKind::Code) {
+        Some((start, limit)) => {
+            cache.code = Some((start, start, limit));
+            MEMORY_OK
+        },
+        None => MEMORY_TOO_BIG
+    }
+}
+extern fn get_limit(memctx: jit_memory_context_t) -> *mut c_void {
+    cache(memctx).code.map(|(_, _, limit)| limit as *mut c_void).unwrap_or(ptr::null_mut())
+}
+extern fn get_break(memctx: jit_memory_context_t) -> *mut c_void {
+    cache(memctx).code.map(|(_, brk, _)| brk as *mut c_void).unwrap_or(ptr::null_mut())
+}
+extern fn set_break(memctx: jit_memory_context_t, brk: *mut c_void) {
+    let cache = cache(memctx);
+    if let Some((start, _, limit)) = cache.code {
+        cache.code = Some((start, brk as *mut u8, limit));
+    }
+}
+extern fn alloc_trampoline(memctx: jit_memory_context_t) -> *mut c_void {
+    inner().alloc_trampoline.unwrap()(cache(memctx).inner)
+}
+extern fn free_trampoline(memctx: jit_memory_context_t, trampoline: *mut c_void) {
+    inner().free_trampoline.unwrap()(cache(memctx).inner, trampoline)
+}
+extern fn alloc_closure(memctx: jit_memory_context_t) -> *mut c_void {
+    inner().alloc_closure.unwrap()(cache(memctx).inner)
+}
+extern fn free_closure(memctx: jit_memory_context_t, closure: *mut c_void) {
+    inner().free_closure.unwrap()(cache(memctx).inner, closure)
+}
+extern fn alloc_data(memctx: jit_memory_context_t, size: jit_size_t, align: jit_size_t) -> *mut c_void {
+    let cache = cache(memctx);
+    let (size, align) = (size as usize, cmp::max(align as usize, 1));
+    if let Some((start, brk, limit)) = cache.data {
+        let ptr = align_up(brk, align);
+        if (limit as usize).saturating_sub(ptr as usize) >= size {
+            cache.data = Some((start, unsafe { ptr.offset(size as isize) }, limit));
+            return ptr as *mut c_void
+        }
+    }
+    match cache.alloc(size + align, RegionKind::Data) {
+        Some((start, limit)) => {
+            let ptr = align_up(start, align);
+            cache.data = Some((start, unsafe { ptr.offset(size as isize) }, limit));
+            ptr as *mut c_void
+        },
+        None => ptr::null_mut()
+    }
+}
+/// The LibJIT memory manager that lays code and data out in the regions given
+/// by a `MemoryManager`
+pub static MANAGER: Struct_jit_memory_manager = Struct_jit_memory_manager {
+    create: Some(create),
+    destroy: Some(destroy),
+    find_function_info: Some(find_function_info),
+    get_function: Some(get_function),
+    get_function_start: Some(get_function_start),
+    get_function_end: Some(get_function_end),
+    alloc_function: Some(alloc_function),
+    free_function: Some(free_function),
+    start_function: Some(start_function),
+    end_function: Some(end_function),
+    extend_limit: Some(extend_limit),
+    get_limit: Some(get_limit),
+    get_break: Some(get_break),
+    set_break: Some(set_break),
+    alloc_trampoline: Some(alloc_trampoline),
+    free_trampoline: Some(free_trampoline),
+    alloc_closure: Some(alloc_closure),
+    free_closure: Some(free_closure),
+    alloc_data: Some(alloc_data)
+};
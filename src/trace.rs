@@ -0,0 +1,133 @@
+use raw::*;
+use context::{is_location_offset, Context, Location};
+use function::Func;
+use util::from_ptr_opt;
+use std::os::raw::{c_uint, c_void};
+use std::marker::PhantomData;
+use std::fmt;
+/// The offset LibJIT gives for code without a marked offset
+const NO_OFFSET: c_uint = !0;
+/// A snapshot of the functions on the call stack
+///
+/// This can be captured from Rust with `StackTrace::capture`, or from JIT code
+/// with `insn_get_call_stack`, which is normally used just before throwing an
+/// exception to record where it was thrown from.
+pub struct StackTrace<'a> {
+    _trace: jit_stack_trace_t,
+    _context: jit_context_t,
+    marker: PhantomData<&'a ()>
+}
+impl<'a> StackTrace<'a> {
+    /// Capture the current call stack, using `ctx` to find the functions on it
+    pub fn capture<T>(ctx: &'a Context<T>) -> StackTrace<'a> {
+        unsafe {
+            StackTrace::from_raw(ctx, jit_exception_get_stack_trace())
+        }
+    }
+    /// Take ownership of a stack trace made by JIT code, like the value given by
+    /// `insn_get_call_stack`
+    pub unsafe fn from_raw<T>(ctx: &'a Context<T>, trace: jit_stack_trace_t) -> StackTrace<'a> {
+        StackTrace {
+            _trace: trace,
+            _context: ctx.into(),
+            marker: PhantomData
+        }
+    }
+    /// Get the number of frames in the stack trace
+    pub fn len(&self) -> usize {
+        unsafe {
+            jit_stack_trace_get_size(self._trace) as usize
+        }
+    }
+    /// Iterate through the frames of the stack trace, from the innermost outwards
+    pub fn frames(&self) -> Frames {
+        Frames {
+            trace: self,
+            index: 0,
+            length: self.len() as c_uint
+        }
+    }
+}
+impl<'a> Drop for StackTrace<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            jit_stack_trace_free(self._trace);
+        }
+    }
+}
+impl<'a> fmt::Display for StackTrace<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (index, frame) in self.frames().enumerate() {
+            try!(writeln!(fmt, "#{} {}", index, frame));
+        }
+        Ok(())
+    }
+}
+impl<'a> fmt::Debug for StackTrace<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+/// A single frame of a stack trace
+pub struct Frame<'a> {
+    /// The JIT function running in this frame, or `None` if this is native code
+    pub function: Option<&'a Func>,
+    /// The native program counter of this frame
+    pub pc: *mut c_void,
+    /// The offset marked with `insn_mark_offset` just before the program
+    /// counter, or `None` if there isn't one
+    pub offset: Option<u32>,
+    /// The source location marked with `insn_mark_location` just before the
+    /// program counter, or `None` if there isn't one
+    pub location: Option<&'a Location>
+}
+impl<'a> fmt::Display for Frame<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(func) => try!(fmt.write_str(func.get_name().unwrap_or("<unnamed function>"))),
+            None => try!(fmt.write_str("<native code>"))
+        }
+        try!(write!(fmt, " at {:?}", self.pc));
+        if let Some(location) = self.location {
+            try!(write!(fmt, ", {}", location));
+        } else if let Some(offset) = self.offset {
+            try!(write!(fmt, ", offset {}", offset));
+        }
+        Ok(())
+    }
+}
+/// Iterates through the frames of a stack trace
+pub struct Frames<'a> {
+    trace: &'a StackTrace<'a>,
+    index: c_uint,
+    length: c_uint
+}
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+    fn next(&mut self) -> Option<Frame<'a>> {
+        if self.index < self.length {
+            let index = self.index;
+            self.index += 1;
+            unsafe {
+                let (trace, context) = (self.trace._trace, self.trace._context);
+                let offset = jit_stack_trace_get_offset(context, trace, index);
+                let ctx: &'a Context = context.into();
+                Some(Frame {
+                    function: from_ptr_opt(jit_stack_trace_get_function(context, trace, index)),
+                    pc: jit_stack_trace_get_pc(trace, index),
+                    offset: if offset == NO_OFFSET || is_location_offset(offset) {
+                        None
+                    } else {
+                        Some(offset as u32)
+                    },
+                    location: ctx.get_location(offset as u32)
+                })
+            }
+        } else {
+            None
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        ((self.length - self.index) as usize, None)
+    }
+}
@@ -0,0 +1,86 @@
+use raw::*;
+use compile::Compile;
+use function::CompiledFunction;
+use types::{CowType, Ty};
+use cbox::CSemiBox;
+use std::os::raw::c_void;
+use std::marker::PhantomData;
+use std::mem;
+/// A list of arguments to a function, as a tuple of their types
+pub trait Args<'a> {
+    /// Get the types of the arguments
+    fn get_types() -> Vec<CowType<'a>>;
+    /// Call `cb` with a pointer to each of the arguments, in order
+    fn with_ptrs<F, T>(self, cb: F) -> T where F:FnOnce(&mut [*mut c_void]) -> T;
+}
+/// Panic if the signature `sig` doesn't take the arguments `A` and return `R`
+pub fn assert_sig<'a, A, R>(sig: &Ty) where A:Args<'a>, R:Compile<'a> {
+    let (args, ret) = (A::get_types(), R::get_type());
+    let matches = sig.params().count() == args.len()
+        && sig.params().zip(args.iter()).all(|(param, arg)| param == &**arg)
+        && sig.get_return() == Some(&*ret);
+    if !matches {
+        panic!("{:?} can't be called with arguments {:?} returning {:?}", sig, args, ret)
+    }
+}
+/// A compiled function that can be called like a Rust function, with the
+/// argument types `A` as a tuple and the return type `R`
+///
+/// The signature of the function is checked when this is made, and this can
+/// only be used while the context the function is in is alive.
+///
+/// ```rust
+/// use jit::*;
+/// let ctx = Context::<()>::new();
+/// let func = UncompiledFunction::new(&ctx, &get::<fn(i32, i32) -> i32>());
+/// func.insn_return(func.insn_mul(&func[0], &func[1]));
+/// let func: TypedFunction<(i32, i32), i32> = TypedFunction::new(UncompiledFunction::compile(func));
+/// assert_eq!(func(6, 7), 42);
+/// ```
+pub struct TypedFunction<'a, A, R> {
+    func: jit_function_t,
+    ptr: *mut c_void,
+    marker: PhantomData<(&'a (), fn(A) -> R)>
+}
+impl<'a, A, R> TypedFunction<'a, A, R> where A:Args<'a>, R:Compile<'a> {
+    /// Make a typed handle to the compiled function given
+    ///
+    /// This panics if the function's signature doesn't take the arguments `A`
+    /// and return `R`, in release builds as well as debug ones.
+    pub fn new(func: CSemiBox<'a, CompiledFunction>) -> TypedFunction<'a, A, R> {
+        assert_sig::<A, R>(func.get_signature());
+        unsafe {
+            let func: jit_function_t = (&*func).into();
+            TypedFunction {
+                func: func,
+                ptr: jit_function_to_closure(func),
+                marker: PhantomData
+            }
+        }
+    }
+    /// Get the compiled function this calls
+    pub fn get_function(&self) -> &'a CompiledFunction {
+        self.func.into()
+    }
+}
+impl<'a, A, R> Clone for TypedFunction<'a, A, R> {
+    fn clone(&self) -> TypedFunction<'a, A, R> {
+        *self
+    }
+}
+impl<'a, A, R> Copy for TypedFunction<'a, A, R> {}
+typed_functions!{
+    () => ();
+    (A) => (a);
+    (A, B) => (a, b);
+    (A, B, C) => (a, b, c);
+    (A, B, C, D) => (a, b, c, d);
+    (A, B, C, D, E) => (a, b, c, d, e);
+    (A, B, C, D, E, F) => (a, b, c, d, e, f);
+    (A, B, C, D, E, F, G) => (a, b, c, d, e, f, g);
+    (A, B, C, D, E, F, G, H) => (a, b, c, d, e, f, g, h);
+    (A, B, C, D, E, F, G, H, I) => (a, b, c, d, e, f, g, h, i);
+    (A, B, C, D, E, F, G, H, I, J) => (a, b, c, d, e, f, g, h, i, j);
+    (A, B, C, D, E, F, G, H, I, J, K) => (a, b, c, d, e, f, g, h, i, j, k);
+    (A, B, C, D, E, F, G, H, I, J, K, L) => (a, b, c, d, e, f, g, h, i, j, k, l)
+}
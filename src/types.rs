@@ -1,7 +1,7 @@
 use raw::*;
 use compile::Compile;
 use function::Abi;
-use  std::os::raw::{c_char, c_uint, c_void};
+use  std::os::raw::{c_char, c_int, c_uint, c_void};
 use util::{from_ptr, from_ptr_opt};
 use std::borrow::*;
 use std::marker::PhantomData;
@@ -12,6 +12,9 @@ use std::ffi::{self, CString};
 use std::ops::{Deref, DerefMut};
 
 pub use kind::TypeKind;
+/// The tag given to the element types of arrays, which holds their length,
+/// and is well clear of the tags LibJIT uses itself
+const ARRAY_TAG: c_int = 20000;
 /// The integer representation of a type
 pub mod kind {
     use 
@@ -45,7 +48,12 @@ std::os::raw::c_int;
 impl fmt::Debug for Ty {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         let kind = self.get_kind();
-        if kind.contains(kind::SysChar) {
+        if let (Some(elem), Some(len)) = (self.get_elem_type(), self.get_array_len()) {
+            write!(fmt, "[{:?}; {}]", elem, len)
+        } else if self.is_tagged() && unsafe { jit_type_get_tagged_kind(self.into()) } == ARRAY_TAG {
+            let elem: &Ty = unsafe { from_ptr(jit_type_get_tagged_type(self.into())) };
+            fmt::Debug::fmt(elem, fmt)
+        } else if kind.contains(kind::SysChar) {
             fmt.write_str("char")
         } else if kind.contains(kind::SysBool) {
             fmt.write_str("bool")
@@ -307,6 +315,15 @@ impl PartialEq for Ty {
     fn eq(&self, other: &Ty) -> bool {
         let self_kind = self.get_kind();
         let other_kind = other.get_kind();
+        if self.is_tagged() && other.is_tagged() {
+            return unsafe {
+                let (self_tagged, other_tagged): (&Ty, &Ty) = (
+                    from_ptr(jit_type_get_tagged_type(self.into())),
+                    from_ptr(jit_type_get_tagged_type(other.into()))
+                );
+                jit_type_get_tagged_kind(self.into()) == jit_type_get_tagged_kind(other.into()) && self_tagged == other_tagged
+            }
+        }
         (self_kind == other_kind && self.is_primitive() || self.is_pointer()) || (
             (self.is_struct() && self.fields().zip(other.fields()).filter(|&(ref self_f, ref other_f)| !self_f.get_type().eq(other_f.get_type())).count() == 0) ||
             (self.is_signature() && self.get_return().unwrap() == other.get_return().unwrap())
@@ -395,6 +412,31 @@ impl Type {
             jit_type_create_union(fields.as_ptr() as *mut jit_type_t, fields.len() as c_uint, 1).into()
         }
     }
+    /// Create a type descriptor for a fixed-size array of `len` elements of the
+    /// type `elem`.
+    ///
+    /// This is laid out as a struct with a field for each element, where each
+    /// field's type is `elem` tagged with the length of the array, so it can
+    /// be told apart from other structs.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let i32_t = get::<i32>();
+    /// let ty = Type::new_array(&i32_t, 4);
+    /// assert_eq!(ty.fields().count(), 4);
+    /// assert_eq!(ty.get_elem_type(), Some(&i32_t as &Ty));
+    /// assert_eq!(ty.get_array_len(), Some(4));
+    /// assert_eq!(Type::new_struct(&[&i32_t, &i32_t]).get_elem_type(), None);
+    /// ```
+    pub fn new_array(elem: &Ty, len: usize) -> Type {
+        unsafe {
+            let elem = jit_type_create_tagged(elem.into(), ARRAY_TAG, len as *mut c_void, None, 1);
+            let fields = vec![elem; len];
+            let ty = jit_type_create_struct(fields.as_ptr() as *mut jit_type_t, len as c_uint, 1);
+            jit_type_free(elem);
+            ty.into()
+        }
+    }
     #[inline(always)]
     /// Create a type descriptor for a pointer to another type.
     pub fn new_pointer(pointee: &Ty) -> Type {
@@ -465,6 +507,39 @@ impl Ty {
         }
     }
     #[inline(always)]
+    /// Set the offset of the field at `index` in this struct or union type,
+    /// instead of letting LibJIT lay it out.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let i32_t = get::<i32>();
+    /// let mut ty = Type::new_struct(&mut [&i32_t, &i32_t]);
+    /// ty.set_offset(1, 8);
+    /// assert_eq!(ty.fields().nth(1).unwrap().get_offset(), 8);
+    /// ```
+    pub fn set_offset(&mut self, index: usize, offset: usize) {
+        unsafe {
+            jit_type_set_offset(self.into(), index as c_uint, offset as jit_nuint)
+        }
+    }
+    #[inline(always)]
+    /// Set the size and alignment of this struct or union type, instead of
+    /// letting LibJIT lay it out.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let i32_t = get::<i32>();
+    /// let mut ty = Type::new_struct(&mut [&i32_t]);
+    /// ty.set_size_and_alignment(16, 8);
+    /// assert_eq!(ty.get_size(), 16);
+    /// assert_eq!(ty.get_alignment(), 8);
+    /// ```
+    pub fn set_size_and_alignment(&mut self, size: usize, alignment: usize) {
+        unsafe {
+            jit_type_set_size_and_alignment(self.into(), size as jit_nint, alignment as jit_nint)
+        }
+    }
+    #[inline(always)]
     /// Iterate over the type's fields
     ///
     /// ```rust
@@ -492,6 +567,40 @@ impl Ty {
     pub fn params(&self) -> Params {
         Params::new(self)
     }
+    /// Get the type of the elements of this array type, if it was made with
+    /// `Type::new_array` and has any elements
+    pub fn get_elem_type(&self) -> Option<&Ty> {
+        self.array_elem().map(|elem| unsafe {
+            from_ptr(jit_type_get_tagged_type(elem.into()))
+        })
+    }
+    /// Get the number of elements of this array type, if it was made with
+    /// `Type::new_array` and has any elements
+    pub fn get_array_len(&self) -> Option<usize> {
+        self.array_elem().map(|elem| unsafe {
+            jit_type_get_tagged_data(elem.into()) as usize
+        })
+    }
+    /// Get the tagged type of the first element of this array type
+    fn array_elem(&self) -> Option<&Ty> {
+        if !self.is_struct() {
+            return None
+        }
+        let elem = match self.fields().next() {
+            Some(field) => field.get_type(),
+            None => return None
+        };
+        unsafe {
+            let is_array = elem.is_tagged()
+                && jit_type_get_tagged_kind(elem.into()) == ARRAY_TAG
+                && jit_type_get_tagged_data(elem.into()) as usize == jit_type_num_fields(self.into()) as usize;
+            if is_array {
+                Some(elem)
+            } else {
+                None
+            }
+        }
+    }
     #[inline]
     /// Find the field/parameter index for a particular name.
     pub fn get_field(&self, name:&str) -> Option<Field> {
@@ -1,13 +1,22 @@
 use libc::*;
 use std::fmt::Error;
 use std::ffi::CStr;
-use std::{mem, ptr, str};
+use std::{mem, process, ptr, str};
+use std::panic::{self, AssertUnwindSafe};
 use std::ops::{Deref, Drop};
 use compile::Compile;
 use types::Ty;
 pub fn oom() -> ! {
     panic!("out of memory")
 }
+/// Run `cb` in a callback made from native code, aborting if it panics since
+/// the panic can't unwind through the native code
+pub fn abort_on_panic<F, R>(cb: F) -> R where F:FnOnce() -> R {
+    match panic::catch_unwind(AssertUnwindSafe(cb)) {
+        Ok(value) => value,
+        Err(_) => process::abort()
+    }
+}
 
 #[inline]
 #[cfg(debug_assertions)]
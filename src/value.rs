@@ -2,8 +2,12 @@ use raw::*;
 use function::UncompiledFunction;
 use types::*;
 use compile::Compile;
+use dynamic::JitValue;
+use exception::BuiltinException;
+use label::Label;
 use context::{Context, ContextMember};
 use std::marker::PhantomData;
+use std::os::raw::c_void;
 use std::{fmt, mem, ptr};
 use std::ops::*;
 use util;
@@ -79,6 +83,67 @@ impl Val {
             jit_value_is_addressable(self.into()) != 0
         }
     }
+    /// Determine if a value is local.  i.e. its scope extends over multiple
+    /// blocks within its function.
+    #[inline]
+    pub fn is_local(&self) -> bool {
+        unsafe {
+            jit_value_is_local(self.into()) != 0
+        }
+    }
+    /// Determine if a value is a constant.
+    #[inline]
+    pub fn is_constant(&self) -> bool {
+        unsafe {
+            jit_value_is_constant(self.into()) != 0
+        }
+    }
+    /// Determine if a value is a parameter of its function.
+    #[inline]
+    pub fn is_parameter(&self) -> bool {
+        unsafe {
+            jit_value_is_parameter(self.into()) != 0
+        }
+    }
+    /// Get the value of this constant, or `None` if this isn't a constant or
+    /// its type can't be represented as a `JitValue`
+    ///
+    /// Arithmetic on two constants of the same type is folded into a constant
+    /// while the function is being built, so this can see through it.
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    /// let six = func.insn_mul(func.insn_of(2i32), func.insn_of(3i32));
+    /// assert_eq!(six.as_constant(), Some(JitValue::I32(6)));
+    /// assert_eq!(func[0].as_constant(), None);
+    /// ```
+    pub fn as_constant(&self) -> Option<JitValue> {
+        if !self.is_constant() {
+            return None
+        }
+        let kind = self.get_type().remove_tags().get_kind();
+        unsafe {
+            let value: jit_value_t = self.into();
+            Some(match kind {
+                kind::SByte => JitValue::I8(jit_value_get_nint_constant(value) as i8),
+                kind::UByte => JitValue::U8(jit_value_get_nint_constant(value) as u8),
+                kind::Short => JitValue::I16(jit_value_get_nint_constant(value) as i16),
+                kind::UShort => JitValue::U16(jit_value_get_nint_constant(value) as u16),
+                kind::Int => JitValue::I32(jit_value_get_nint_constant(value) as i32),
+                kind::UInt => JitValue::U32(jit_value_get_nint_constant(value) as u32),
+                kind::NInt => JitValue::ISize(jit_value_get_nint_constant(value) as isize),
+                kind::NUInt => JitValue::USize(jit_value_get_nint_constant(value) as usize),
+                kind::Long => JitValue::I64(jit_value_get_long_constant(value) as i64),
+                kind::ULong => JitValue::U64(jit_value_get_long_constant(value) as u64),
+                kind::Float32 => JitValue::F32(jit_value_get_float32_constant(value)),
+                kind::Float64 => JitValue::F64(jit_value_get_float64_constant(value)),
+                kind::Pointer | kind::Signature => JitValue::Ptr(jit_value_get_nint_constant(value) as *mut c_void),
+                _ => return None
+            })
+        }
+    }
     /// Set a flag on a value to indicate that it is addressable.
     /// This should be used when you want to take the address of a value (e.g.
     /// `&variable` in Rust/C).  The value is guaranteed to not be stored in a
@@ -89,6 +154,186 @@ impl Val {
             jit_value_set_addressable(self.into())
         }
     }
+    /// Get the address of the first element of this array, or this pointer,
+    /// and the type of its elements
+    ///
+    /// A pointer to an array is indexed by the elements of the array, and a
+    /// pointer to anything else is indexed by whole values it points to, like
+    /// in C.
+    fn elem_base(&self) -> (&Val, &Ty) {
+        let func = self.get_function();
+        let ty = self.get_type();
+        if let Some(pointee) = ty.get_ref() {
+            (self, pointee.get_elem_type().unwrap_or(pointee))
+        } else if let Some(elem) = ty.get_elem_type() {
+            self.set_addressable();
+            (func.insn_address_of(self), elem)
+        } else {
+            panic!("{:?} cannot be indexed", ty)
+        }
+    }
+    /// Get the element at `index` of this array, or of the array this points to
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    /// let table = func.insn_of([1i32, 2, 4, 8]);
+    /// func.insn_return(table.get_elem(&func[0]));
+    /// let func = UncompiledFunction::compile(func);
+    /// assert_eq!(func.try_call::<(i32,), i32>((2,)), Ok(4));
+    /// ```
+    pub fn get_elem(&self, index: &Val) -> &Val {
+        let func = self.get_function();
+        let (base, elem) = self.elem_base();
+        func.insn_load_elem(base, index, elem)
+    }
+    /// Set the element at `index` of this array, or of the array this points to
+    pub fn set_elem(&self, index: &Val, value: &Val) {
+        let func = self.get_function();
+        let (base, elem) = self.elem_base();
+        func.insn_store_elem(base, index, func.insn_convert(value, elem, false))
+    }
+    /// Get the length of this slice
+    ///
+    /// This panics if this isn't a slice.
+    pub fn len(&self) -> &Val {
+        match self.load_field("len") {
+            Some(len) => len,
+            None => panic!("{:?} has no length", self.get_type())
+        }
+    }
+    /// Get the discriminant of this enum, or of the enum this points to
+    ///
+    /// Enums without fields are compiled as their integer discriminant by
+    /// `#[derive(Compile)]`, so for an integer this is the integer itself.
+    /// Otherwise this panics if this isn't an enum laid out as a tag and a
+    /// union of variants, like `#[derive(Compile)]` does for enums with fields.
+    pub fn discriminant(&self) -> &Val {
+        let func = self.get_function();
+        let ty = self.get_type();
+        if ty.is_int() {
+            return self
+        }
+        if let Some(pointee) = ty.get_ref() {
+            if pointee.is_int() {
+                return func.insn_load_relative(self, 0, pointee)
+            }
+        }
+        let (base, tag, _) = self.tagged_union();
+        func.insn_load_relative(base, tag.get_offset(), tag.get_type())
+    }
+    /// Check if this enum, or the enum this points to, holds the variant called `variant`
+    ///
+    /// Enums without fields don't keep the names of their variants, so compare
+    /// their `discriminant` with the variant cast to an integer instead. This
+    /// panics if this isn't an enum with fields or it has no variant called `variant`.
+    pub fn is_variant(&self, variant: &str) -> &Val {
+        let func = self.get_function();
+        let (base, tag, payload) = self.tagged_union();
+        let index = match payload.get_type().get_field(variant) {
+            Some(variant) => variant.index as usize,
+            None => panic!("{:?} has no variant {:?}", self.get_type(), variant)
+        };
+        let discriminant = func.insn_load_relative(base, tag.get_offset(), tag.get_type());
+        func.insn_eq(discriminant, func.insn_convert(func.insn_of(index), tag.get_type(), false))
+    }
+    /// Get the field called `field` of the variant called `variant` of this
+    /// enum, or of the enum this points to
+    ///
+    /// Fields of tuple variants are named by their index, so the first field
+    /// of `Some(x)` is `"0"`. This doesn't check the enum holds `variant`, so
+    /// check with `is_variant` first. This panics if this isn't an enum or it
+    /// has no such field.
+    pub fn variant_field(&self, variant: &str, field: &str) -> &Val {
+        let func = self.get_function();
+        let (base, _, payload) = self.tagged_union();
+        let fields = match payload.get_type().get_field(variant) {
+            Some(variant) => variant.get_type(),
+            None => panic!("{:?} has no variant {:?}", self.get_type(), variant)
+        };
+        match fields.get_field(field) {
+            Some(field) => func.insn_load_relative(base, payload.get_offset() + field.get_offset(), field.get_type()),
+            None => panic!("variant {:?} of {:?} has no field {:?}", variant, self.get_type(), field)
+        }
+    }
+    /// Get a pointer to this enum, or the enum this points to, along with its
+    /// tag and payload fields
+    fn tagged_union(&self) -> (&Val, Field, Field) {
+        let func = self.get_function();
+        let ty = self.get_type();
+        let (is_pointer, ty) = match ty.get_ref() {
+            Some(pointee) => (true, pointee),
+            None => (false, ty)
+        };
+        let (tag, payload) = match (ty.get_field("tag"), ty.get_field("payload")) {
+            (Some(tag), Some(payload)) if ty.is_struct() && payload.get_type().is_union() => (tag, payload),
+            _ => panic!("{:?} is not an enum", ty)
+        };
+        let base = if is_pointer {
+            self
+        } else {
+            self.set_addressable();
+            func.insn_address_of(self)
+        };
+        (base, tag, payload)
+    }
+    /// Load the field called `name` of this struct, or of the struct this points to
+    fn load_field(&self, name: &str) -> Option<&Val> {
+        let func = self.get_function();
+        let ty = self.get_type();
+        let (is_pointer, ty) = match ty.get_ref() {
+            Some(pointee) => (true, pointee),
+            None => (false, ty)
+        };
+        ty.get_field(name).map(|field| {
+            let base = if is_pointer {
+                self
+            } else {
+                self.set_addressable();
+                func.insn_address_of(self)
+            };
+            func.insn_load_relative(base, field.get_offset(), field.get_type())
+        })
+    }
+    /// Make sure `index` is within this slice, throwing `OutOfBounds` if it isn't,
+    /// and get the pointer to its elements and the index as a `usize`
+    fn check_bounds(&self, index: &Val) -> (&Val, &Val) {
+        let func = self.get_function();
+        let index = func.insn_convert(index, &::get::<usize>(), false);
+        let mut in_bounds = Label::new(func);
+        func.insn_branch_if(func.insn_lt(index, self.len()), &mut in_bounds);
+        func.insn_throw_builtin(BuiltinException::OutOfBounds);
+        func.insn_label(&mut in_bounds);
+        (self.load_field("ptr").unwrap(), index)
+    }
+    /// Get the element at `index` of this slice, throwing `OutOfBounds` if
+    /// `index` is past its end
+    ///
+    /// ```rust
+    /// use jit::*;
+    /// let ctx = Context::<()>::new();
+    /// let func = UncompiledFunction::new(&ctx, &get::<fn(&[i32], usize) -> i32>());
+    /// func.insn_return(func[0].index_load(&func[1]));
+    /// let func = UncompiledFunction::compile(func);
+    /// let values: &[i32] = &[3, 5, 7];
+    /// assert_eq!(func.try_call::<(&[i32], usize), i32>((values, 1)), Ok(5));
+    /// assert_eq!(func.try_call::<(&[i32], usize), i32>((values, 3)),
+    ///            Err(JitException::Builtin(BuiltinException::OutOfBounds)));
+    /// ```
+    pub fn index_load(&self, index: &Val) -> &Val {
+        let func = self.get_function();
+        let (ptr, index) = self.check_bounds(index);
+        func.insn_load_elem(ptr, index, ptr.get_type().get_ref().unwrap())
+    }
+    /// Set the element at `index` of this slice, throwing `OutOfBounds` if
+    /// `index` is past its end
+    pub fn index_store(&self, index: &Val, value: &Val) {
+        let func = self.get_function();
+        let (ptr, index) = self.check_bounds(index);
+        let elem = ptr.get_type().get_ref().unwrap();
+        func.insn_store_elem(ptr, index, func.insn_convert(value, elem, false))
+    }
 }
 impl Index<usize> for Val {
     type Output = Val;
@@ -98,7 +343,7 @@ impl Index<usize> for Val {
         while let Some(elem) = ty.get_ref() {
             ty = elem;
         }
-        if !ty.is_struct() {
+        if !ty.is_struct() && !ty.is_union() {
             panic!("{:?} cannot be indexed", ty);
         } else if let Some(field) = ty.fields().nth(index) {
             func.insn_load_relative(self, field.get_offset(), field.get_type())
@@ -115,7 +360,7 @@ impl<'a> Index<&'a str> for Val {
         while let Some(elem) = ty.get_ref() {
             ty = elem;
         }
-        if !ty.is_struct() {
+        if !ty.is_struct() && !ty.is_union() {
             panic!("{:?} cannot be indexed", ty);
         } else if let Some(field) = ty.get_field(index) {
             func.insn_load_relative(self, field.get_offset(), field.get_type())
@@ -588,6 +588,10 @@ extern "C" {
                                 pc: *mut c_void,
                                 handler: *mut *mut c_void) ->
      jit_function_t;
+    pub fn _jit_function_get_bytecode(func: jit_function_t,
+                                      func_info: *mut c_void,
+                                      pc: *mut c_void,
+                                      exact: c_int) -> c_ulong;
     pub fn jit_function_to_vtable_pointer(func: jit_function_t) ->
      *mut c_void;
     pub fn jit_function_from_vtable_pointer(context: jit_context_t,
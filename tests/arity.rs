@@ -0,0 +1,41 @@
+extern crate jit;
+use jit::*;
+
+#[test]
+fn test_many_params() {
+    let ctx = Context::<()>::new();
+    let sig = get::<fn(i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32) -> i32>();
+    assert_eq!(sig.params().count(), 12);
+    let func = UncompiledFunction::new(&ctx, &sig);
+    {
+        let mut sum = &func[0];
+        for i in 1..12 {
+            sum = func.insn_add(sum, func.insn_mul(&func[i], func.insn_of(i as i32 + 1)));
+        }
+        func.insn_return(sum);
+    }
+    let func: TypedFunction<(i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32), i32> =
+        TypedFunction::new(UncompiledFunction::compile(func));
+    assert_eq!(func(1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1), 78);
+}
+
+#[test]
+fn test_many_tuple_elements() {
+    let ty = get::<(f64, f64, f64, f64, f64, f64, f64, f64)>();
+    assert_eq!(ty.fields().count(), 8);
+    assert_eq!(ty.get_size(), 64);
+}
+
+#[test]
+fn test_call_rust_many_args() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i64, i64, i64, i64, i64, i64) -> i64>());
+    let weigh = |a: i64, b: i64, c: i64, d: i64, e: i64, f: i64| a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f;
+    {
+        let args: Vec<&Val> = (0..6).map(|i| &func[i]).collect();
+        let result = func.insn_call_rust(Some("weigh"), &weigh, &args, flags::CallFlags::empty());
+        func.insn_return(result);
+    }
+    let func: TypedFunction<(i64, i64, i64, i64, i64, i64), i64> = TypedFunction::new(UncompiledFunction::compile(func));
+    assert_eq!(func(1, 1, 1, 1, 1, 1), 21);
+}
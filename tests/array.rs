@@ -0,0 +1,94 @@
+extern crate jit;
+use jit::*;
+use std::cell::Cell;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+
+#[test]
+fn test_array_type() {
+    let ty = get::<[u16; 3]>();
+    assert_eq!(ty.get_size(), mem::size_of::<[u16; 3]>());
+    assert_eq!(ty.get_alignment(), mem::align_of::<[u16; 3]>());
+    assert_eq!(ty.fields().count(), 3);
+    assert_eq!(ty.get_elem_type(), Some(&get::<u16>() as &Ty));
+    assert_eq!(ty.get_array_len(), Some(3));
+    assert_eq!(format!("{:?}", ty), "[u16; 3]");
+    assert_eq!(get::<[f64; 0]>().get_size(), 0);
+}
+
+#[test]
+fn test_array_elements() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> f64>());
+    {
+        let table = func.insn_of([0.5f64, 1.5, 2.5, 3.5]);
+        table.set_elem(func.insn_of(3i32), func.insn_of(10.0f64));
+        func.insn_return(table.get_elem(&func[0]));
+    }
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(i32,), f64>((1,)), Ok(1.5));
+    assert_eq!(func.try_call::<(i32,), f64>((3,)), Ok(10.0));
+}
+
+#[test]
+fn test_array_pointer() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut [i32; 4], i32)>());
+    {
+        let elem = func[0].get_elem(&func[1]);
+        func[0].set_elem(&func[1], func.insn_mul(elem, func.insn_of(2i32)));
+        func.insn_default_return();
+    }
+    let func = UncompiledFunction::compile(func);
+    let mut values = [1, 2, 3, 4];
+    func.try_call::<(*mut [i32; 4], i32), ()>((&mut values, 2)).unwrap();
+    assert_eq!(values, [1, 2, 6, 4]);
+}
+
+#[test]
+fn test_struct_pointer() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*const (i32, i32), i32) -> i32>());
+    {
+        let pair = func[0].get_elem(&func[1]);
+        pair.set_addressable();
+        let second = get::<(i32, i32)>().fields().nth(1).unwrap().get_offset();
+        func.insn_return(func.insn_load_relative(func.insn_address_of(pair), second, &get::<i32>()));
+    }
+    let func = UncompiledFunction::compile(func);
+    let pairs = [(1, 2), (3, 4)];
+    assert_eq!(func.try_call::<(*const (i32, i32), i32), i32>((pairs.as_ptr(), 1)), Ok(4));
+}
+
+thread_local!(static DROPS: Cell<usize> = Cell::new(0));
+
+/// An element that can't be compiled if it's negative, and counts its drops
+struct Fuse(i32);
+impl Drop for Fuse {
+    fn drop(&mut self) {
+        DROPS.with(|drops| drops.set(drops.get() + 1));
+    }
+}
+impl<'a> Compile<'a> for Fuse {
+    fn compile(self, func: &'a UncompiledFunction) -> &'a Val {
+        if self.0 < 0 {
+            panic!("negative fuse")
+        }
+        func.insn_of(self.0)
+    }
+    fn get_type() -> CowType<'a> {
+        get::<i32>()
+    }
+}
+
+#[test]
+fn test_array_compile_panic() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn()>());
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        func.insn_of([Fuse(1), Fuse(-1), Fuse(2)]);
+    }));
+    assert!(result.is_err());
+    // the last element is leaked, but none are dropped twice
+    assert_eq!(DROPS.with(|drops| drops.get()), 2);
+}
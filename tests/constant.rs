@@ -0,0 +1,41 @@
+extern crate jit;
+use jit::*;
+use std::i32;
+
+#[test]
+fn test_value_kinds() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    let param = &func[0];
+    let constant = func.insn_of(7i32);
+    assert!(param.is_parameter());
+    assert!(!param.is_constant());
+    assert!(constant.is_constant());
+    assert!(!constant.is_parameter());
+    assert!(!constant.is_local());
+    assert_eq!(constant.as_constant(), Some(JitValue::I32(7)));
+    assert_eq!(func.insn_of(2.5f64).as_constant(), Some(JitValue::F64(2.5)));
+    assert_eq!(func.insn_of(-3i64).as_constant(), Some(JitValue::I64(-3)));
+    assert_eq!(param.as_constant(), None);
+}
+
+#[test]
+fn test_constant_folding() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    let sum = func.insn_add(func.insn_of(2i32), func.insn_of(3i32));
+    assert_eq!(sum.as_constant(), Some(JitValue::I32(5)));
+    let product = func.insn_mul(sum, func.insn_of(4i32));
+    assert_eq!(product.as_constant(), Some(JitValue::I32(20)));
+    let wrapped = func.insn_add(func.insn_of(i32::MAX), func.insn_of(1i32));
+    assert_eq!(wrapped.as_constant(), Some(JitValue::I32(i32::MIN)));
+    let float = func.insn_div(func.insn_of(1.0f32), func.insn_of(4.0f32));
+    assert_eq!(float.as_constant(), Some(JitValue::F32(0.25)));
+    let bits = func.insn_xor(func.insn_of(0b1100u64), func.insn_of(0b1010u64));
+    assert_eq!(bits.as_constant(), Some(JitValue::U64(0b0110)));
+    assert!(!func.insn_div(func.insn_of(1i32), func.insn_of(0i32)).is_constant());
+    assert!(!func.insn_add(&func[0], product).is_constant());
+    func.insn_return(func.insn_sub(&func[0], product));
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(i32,), i32>((25,)), Ok(5));
+}
@@ -0,0 +1,102 @@
+extern crate jit;
+use jit::*;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[test]
+fn test_debug_hook() {
+    let ctx = Context::<()>::new();
+    let debugger = Debugger::new(&ctx);
+    let marks = Arc::new(Mutex::new(Vec::new()));
+    let hook_marks = marks.clone();
+    debugger.set_hook(move |func, data1, data2| {
+        hook_marks.lock().unwrap().push((func.get_name().map(String::from), data1, data2));
+    });
+    let func = UncompiledFunction::new(&ctx, &get::<fn(isize)>());
+    func.set_name("traced");
+    func.insn_mark_breakpoint(1, 2);
+    func.insn_mark_breakpoint_variable(func.insn_of(3isize), &func[0]);
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(isize,), ()>((4,)), Ok(()));
+    assert_eq!(*marks.lock().unwrap(), vec![
+        (Some(String::from("traced")), 1, 2),
+        (Some(String::from("traced")), 3, 4)
+    ]);
+}
+
+/// Wait for the next event of the kind given, skipping any others
+fn wait_for<'a>(debugger: &Debugger<'a>, kind: EventKind) -> Event<'a> {
+    loop {
+        let event = debugger.wait_event(Some(10000)).expect("timed out waiting for a debugger event");
+        if event.kind == kind {
+            return event
+        }
+    }
+}
+
+#[test]
+fn test_breakpoint() {
+    let ctx = Context::<()>::new();
+    // the thread running the JIT code needs the context, which outlives it
+    let ctx_ref: &'static Context = unsafe { mem::transmute(&*ctx) };
+    let debugger = Arc::new(Debugger::new(ctx_ref));
+    debugger.add_breakpoint(Breakpoint {
+        data1: Some(marks::LINE),
+        data2: Some(1),
+        ..Default::default()
+    });
+    let runner = {
+        let debugger = debugger.clone();
+        thread::spawn(move || {
+            debugger.attach_self(false);
+            let func = UncompiledFunction::new(ctx_ref, &get::<fn()>());
+            func.set_name("stepped");
+            func.insn_mark_breakpoint(marks::LINE, 1);
+            func.insn_mark_breakpoint(marks::LINE, 2);
+            func.insn_default_return();
+            let func = UncompiledFunction::compile(func);
+            let result = func.try_call::<(), ()>(());
+            debugger.detach_self();
+            result.is_ok()
+        })
+    };
+    let event = wait_for(&debugger, EventKind::HardBreakpoint);
+    assert_eq!(event.function.and_then(|func| func.get_name()), Some("stepped"));
+    assert_eq!((event.data1, event.data2), (marks::LINE, 1));
+    assert!(!debugger.is_running(event.thread));
+    debugger.step(event.thread);
+    let event = wait_for(&debugger, EventKind::SoftBreakpoint);
+    assert_eq!((event.data1, event.data2), (marks::LINE, 2));
+    debugger.run(event.thread);
+    assert!(runner.join().unwrap());
+}
+
+#[test]
+#[should_panic]
+fn test_one_debugger() {
+    let ctx = Context::<()>::new();
+    let _debugger = Debugger::new(&ctx);
+    Debugger::new(&ctx);
+}
+
+#[test]
+fn test_debug_hook_removed_on_drop() {
+    let ctx = Context::<()>::new();
+    let marks = Arc::new(Mutex::new(0));
+    {
+        let debugger = Debugger::new(&ctx);
+        let hook_marks = marks.clone();
+        debugger.set_hook(move |_, _, _| {
+            *hook_marks.lock().unwrap() += 1;
+        });
+    }
+    assert_eq!(Arc::strong_count(&marks), 1);
+    let func = UncompiledFunction::new(&ctx, &get::<fn()>());
+    func.insn_mark_breakpoint(1, 2);
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(), ()>(()), Ok(()));
+    assert_eq!(*marks.lock().unwrap(), 0);
+}
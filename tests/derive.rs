@@ -0,0 +1,251 @@
+extern crate jit;
+#[macro_use]
+extern crate jit_derive;
+use jit::*;
+use std::mem;
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct Padded {
+    tag: u8,
+    value: f64,
+    count: u16
+}
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct Nested {
+    flag: bool,
+    inner: Padded
+}
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct Pair<A, B> {
+    first: A,
+    second: B
+}
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct Position(f32, f64);
+
+#[test]
+fn test_derive_layout() {
+    let ty = get::<Padded>();
+    assert_eq!(ty.get_size(), mem::size_of::<Padded>());
+    assert_eq!(ty.get_alignment(), mem::align_of::<Padded>());
+    let offsets: Vec<usize> = ty.fields().map(|field| field.get_offset()).collect();
+    assert_eq!(offsets, vec![0, 8, 16]);
+    assert_eq!(ty.get_field("value").unwrap().get_type(), &get::<f64>() as &Ty);
+    let ty = get::<Nested>();
+    assert_eq!(ty.get_size(), mem::size_of::<Nested>());
+    assert_eq!(ty.get_field("inner").unwrap().get_offset(), 8);
+    assert_eq!(get::<Position>().get_size(), mem::size_of::<Position>());
+    assert_eq!(get::<Pair<u8, u32>>().fields().nth(1).unwrap().get_offset(), 4);
+}
+
+#[test]
+fn test_derive_read_fields() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*const Nested) -> f64>());
+    {
+        let nested = &func[0];
+        let nested_t = get::<Nested>();
+        let padded_t = get::<Padded>();
+        let inner = nested_t.get_field("inner").unwrap().get_offset();
+        let value = func.insn_load_relative(nested, inner + padded_t.get_field("value").unwrap().get_offset(), &get::<f64>());
+        let count = func.insn_load_relative(nested, inner + padded_t.get_field("count").unwrap().get_offset(), &get::<u16>());
+        func.insn_return(func.insn_add(value, func.insn_convert(count, &get::<f64>(), false)));
+    }
+    let func = UncompiledFunction::compile(func);
+    let nested = Nested {
+        flag: true,
+        inner: Padded { tag: 3, value: 1.5, count: 4 }
+    };
+    assert_eq!(func.try_call::<(*const Nested,), f64>((&nested,)), Ok(5.5));
+}
+
+#[test]
+fn test_derive_compile() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut Padded)>());
+    let padded = Padded { tag: 7, value: -2.25, count: 300 };
+    func.insn_store_relative(&func[0], 0, padded.compile(&func));
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    let mut out = Padded { tag: 0, value: 0.0, count: 0 };
+    assert_eq!(func.try_call::<(*mut Padded,), ()>((&mut out,)), Ok(()));
+    assert_eq!(out, padded);
+}
+
+#[test]
+fn test_derive_generic() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*const Pair<u8, Position>) -> f64>());
+    {
+        let pair = &func[0];
+        let second = get::<Pair<u8, Position>>().get_field("second").unwrap().get_offset();
+        let position_t = get::<Position>();
+        func.insn_return(func.insn_load_relative(pair, second + position_t.fields().nth(1).unwrap().get_offset(), &get::<f64>()));
+    }
+    let func = UncompiledFunction::compile(func);
+    let pair = Pair { first: 1u8, second: Position(2.0, 3.5) };
+    assert_eq!(func.try_call::<(*const Pair<u8, Position>,), f64>((&pair,)), Ok(3.5));
+}
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+enum Op {
+    Add,
+    Sub
+}
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(C, u8)]
+enum Expr {
+    Num(f64),
+    Bin { op: Op, left: i32, right: i32 },
+    Nil
+}
+
+#[test]
+fn test_derive_enum_layout() {
+    assert_eq!(get::<Op>().get_kind(), get::<u8>().get_kind());
+    let ty = get::<Expr>();
+    assert_eq!(ty.get_size(), mem::size_of::<Expr>());
+    assert_eq!(ty.get_alignment(), mem::align_of::<Expr>());
+    let payload = ty.get_field("payload").unwrap();
+    assert_eq!(payload.get_offset(), 8);
+    assert!(payload.get_type().is_union());
+    assert_eq!(payload.get_type().get_field("Bin").unwrap().get_type().get_field("right").unwrap().get_offset(), 8);
+}
+
+#[test]
+fn test_derive_enum_match() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*const Expr) -> f64>());
+    {
+        let expr = &func[0];
+        let mut not_num = Label::new(&func);
+        let mut not_bin = Label::new(&func);
+        func.insn_branch_if_not(expr.is_variant("Num"), &mut not_num);
+        func.insn_return(expr.variant_field("Num", "0"));
+        func.insn_label(&mut not_num);
+        func.insn_branch_if_not(expr.is_variant("Bin"), &mut not_bin);
+        let sum = func.insn_add(expr.variant_field("Bin", "left"), expr.variant_field("Bin", "right"));
+        func.insn_return(func.insn_convert(sum, &get::<f64>(), false));
+        func.insn_label(&mut not_bin);
+        func.insn_return(func.insn_of(-1.0f64));
+    }
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(*const Expr,), f64>((&Expr::Num(2.5),)), Ok(2.5));
+    assert_eq!(func.try_call::<(*const Expr,), f64>((&Expr::Bin { op: Op::Add, left: 2, right: 3 },)), Ok(5.0));
+    assert_eq!(func.try_call::<(*const Expr,), f64>((&Expr::Nil,)), Ok(-1.0));
+}
+
+#[test]
+fn test_derive_enum_compile() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut Expr) -> u8>());
+    let expr = Expr::Bin { op: Op::Sub, left: -4, right: 9 };
+    func.insn_store_relative(&func[0], 0, expr.compile(&func));
+    func.insn_return(func.insn_of(expr).discriminant());
+    let func = UncompiledFunction::compile(func);
+    let mut out = Expr::Nil;
+    assert_eq!(func.try_call::<(*mut Expr,), u8>((&mut out,)), Ok(1));
+    assert_eq!(out, expr);
+}
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(C, u8)]
+enum Level {
+    Low = 5,
+    Middle,
+    High = 20
+}
+
+#[test]
+fn test_derive_enum_discriminants() {
+    assert_eq!(get::<Level>().get_kind(), get::<u8>().get_kind());
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*const Level) -> u8>());
+    {
+        let level = &func[0];
+        let mut not_high = Label::new(&func);
+        func.insn_branch_if_not(func.insn_eq(level.discriminant(), func.insn_of(Level::High)), &mut not_high);
+        func.insn_return(func.insn_of(Level::Middle));
+        func.insn_label(&mut not_high);
+        func.insn_return(level.discriminant());
+    }
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(*const Level,), u8>((&Level::Low,)), Ok(5));
+    assert_eq!(func.try_call::<(*const Level,), u8>((&Level::Middle,)), Ok(6));
+    assert_eq!(func.try_call::<(*const Level,), u8>((&Level::High,)), Ok(6));
+}
+
+#[derive(Compile, Copy, Clone)]
+#[repr(C)]
+union Boxed {
+    float: f64,
+    bits: u64,
+    int: i32
+}
+
+#[test]
+fn test_derive_union_layout() {
+    let ty = get::<Boxed>();
+    assert!(ty.is_union());
+    assert_eq!(ty.get_size(), mem::size_of::<Boxed>());
+    assert!(ty.fields().all(|field| field.get_offset() == 0));
+    assert_eq!(ty.get_field("int").unwrap().get_type(), &get::<i32>() as &Ty);
+}
+
+#[test]
+fn test_derive_union_fields() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut Boxed, f64) -> u64>());
+    func.insn_store_relative(&func[0], 0, Boxed { float: 2.5 }.compile(&func));
+    func.insn_return(func.insn_add(&func[0]["bits"], func.insn_convert(&func[1], &get::<u64>(), false)));
+    let func = UncompiledFunction::compile(func);
+    let mut out = Boxed { int: 0 };
+    let bits: u64 = unsafe { mem::transmute(2.5f64) };
+    assert_eq!(func.try_call::<(*mut Boxed, f64), u64>((&mut out as *mut Boxed, 3.0)), Ok(bits + 3));
+    assert_eq!(unsafe { out.float }, 2.5);
+}
+
+#[derive(Compile, Copy, Clone)]
+#[repr(C)]
+union Triple {
+    bytes: [u8; 3],
+    first: u8
+}
+
+#[test]
+fn test_derive_union_odd_size() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut Triple)>());
+    func.insn_store_relative(&func[0], 0, Triple { bytes: [1, 2, 3] }.compile(&func));
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    let mut out = Triple { bytes: [0; 3] };
+    assert_eq!(func.try_call::<(*mut Triple,), ()>((&mut out as *mut Triple,)), Ok(()));
+    assert_eq!(unsafe { out.bytes }, [1, 2, 3]);
+}
+
+#[derive(Compile, Copy, Clone, Debug, PartialEq)]
+#[repr(C, align(32))]
+struct Aligned {
+    x: f64,
+    y: f64
+}
+
+#[test]
+fn test_derive_aligned_return() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(f64) -> Aligned>());
+    func.insn_return(func.insn_of(Aligned { x: 1.5, y: 0.0 }));
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(get::<Aligned>().get_alignment(), 32);
+    assert_eq!(func.try_call::<(f64,), Aligned>((2.0,)), Ok(Aligned { x: 1.5, y: 0.0 }));
+}
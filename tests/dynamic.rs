@@ -0,0 +1,50 @@
+extern crate jit;
+use jit::*;
+
+extern fn scale(x: f64, n: i32) -> f64 {
+    x * n as f64
+}
+
+extern fn negate(x: *mut i32) {
+    unsafe {
+        *x = -*x;
+    }
+}
+
+#[test]
+fn test_dynamic_call() {
+    let sig = get::<fn(f64, i32) -> f64>();
+    let call = unsafe { DynamicCall::new(scale as *mut _, &sig) };
+    assert_eq!(call.call(&[JitValue::F64(1.5), JitValue::I32(4)]), Ok(JitValue::F64(6.0)));
+    match call.call(&[JitValue::F64(1.5), JitValue::F64(4.0)]) {
+        Err(ApplyError::ArgumentType { index, .. }) => assert_eq!(index, 1),
+        result => panic!("expected a type error, got {:?}", result)
+    }
+    assert_eq!(call.call(&[]), Err(ApplyError::ArgumentCount { expected: 2, got: 0 }));
+}
+
+#[test]
+fn test_dynamic_call_pointer() {
+    let sig = get::<fn(*mut i32)>();
+    let mut x = 5;
+    let call = unsafe { DynamicCall::new(negate as *mut _, &sig) };
+    let ptr = &mut x as *mut i32 as *mut _;
+    assert_eq!(call.call(&[JitValue::Ptr(ptr)]), Ok(JitValue::Void));
+    assert_eq!(x, -5);
+}
+
+#[test]
+fn test_apply_dynamic() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i64, i64) -> i64>());
+    func.insn_return(func.insn_div(&func[0], &func[1]));
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.apply_dynamic(&[JitValue::I64(12), JitValue::I64(4)]), Ok(JitValue::I64(3)));
+    assert_eq!(func.apply_dynamic(&[JitValue::I64(12), JitValue::I64(0)]),
+               Err(ApplyError::Exception(JitException::Builtin(BuiltinException::DivisionByZero))));
+    assert_eq!(func.apply_dynamic(&[JitValue::I64(12)]), Err(ApplyError::ArgumentCount { expected: 2, got: 1 }));
+    match func.apply_dynamic(&[JitValue::I64(12), JitValue::F64(4.0)]) {
+        Err(ApplyError::ArgumentType { index: 1, got: JitValue::F64(_), .. }) => (),
+        result => panic!("expected a type error, got {:?}", result)
+    }
+}
@@ -0,0 +1,41 @@
+extern crate jit;
+use jit::*;
+
+#[test]
+fn test_elem_loop() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut u8, i32) -> i32>());
+    {
+        let (base, len) = (&func[0], &func[1]);
+        let sum = Val::new(&func, &get::<i32>());
+        let i = Val::new(&func, &get::<i32>());
+        func.insn_store(sum, func.insn_of(0i32));
+        func.insn_store(i, func.insn_of(0i32));
+        let mut start = Label::new(&func);
+        let mut end = Label::new(&func);
+        func.insn_label(&mut start);
+        func.insn_branch_if_not(func.insn_lt(i, len), &mut end);
+        let elem = func.insn_load_small(func.insn_load_elem(base, i, &get::<u8>()));
+        func.insn_store(sum, func.insn_add(sum, elem));
+        func.insn_store_elem(base, i, func.insn_convert(func.insn_of(0i32), &get::<u8>(), false));
+        func.insn_store(i, func.insn_add(i, func.insn_of(1i32)));
+        func.insn_branch(&mut start);
+        func.insn_label(&mut end);
+        func.insn_return(sum);
+    }
+    let func = UncompiledFunction::compile(func);
+    let mut bytes = [200u8, 100, 50];
+    assert_eq!(func.try_call::<(*mut u8, i32), i32>((bytes.as_mut_ptr(), 3)), Ok(350));
+    assert_eq!(bytes, [0, 0, 0]);
+}
+
+#[test]
+fn test_elem_address() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut i64, i32) -> *mut i64>());
+    func.insn_return(func.insn_load_elem_address(&func[0], &func[1], &get::<i64>()));
+    let func = UncompiledFunction::compile(func);
+    let mut values = [1i64, 2, 3];
+    let base = values.as_mut_ptr();
+    assert_eq!(func.try_call::<(*mut i64, i32), *mut i64>((base, 2)), Ok(unsafe { base.offset(2) }));
+}
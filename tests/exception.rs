@@ -0,0 +1,256 @@
+#[no_link] #[macro_use]
+extern crate jit_macros;
+extern crate jit;
+use jit::*;
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::thread;
+
+#[test]
+fn test_try_catch() {
+    let mut ctx = Context::<()>::new();
+    jit_func!(&mut ctx, func, fn(x: i32) -> i32 {
+        func.build_try(|| {
+            func.build_if(func.insn_eq(x, func.insn_of(0i32)), || {
+                func.insn_throw(func.insn_of(7usize));
+            });
+            func.insn_return(x);
+        }, |exc| {
+            func.insn_return(func.insn_convert(exc, &get::<i32>(), false));
+        });
+        func.insn_default_return();
+    }, {
+        assert_eq!(func(3), 3);
+        assert_eq!(func(0), 7);
+    });
+}
+
+#[test]
+fn test_try_catch_twice() {
+    let mut ctx = Context::<()>::new();
+    jit_func!(&mut ctx, func, fn(x: i32) -> i32 {
+        let result = Val::new(func, &get::<i32>());
+        func.insn_store(result, func.insn_of(0i32));
+        func.build_try(|| {
+            func.insn_throw(func.insn_of(1usize));
+        }, |_| {
+            func.insn_store(result, func.insn_add(result, func.insn_of(1i32)));
+        });
+        func.build_try(|| {
+            func.build_if(func.insn_lt(x, func.insn_of(0i32)), || {
+                func.insn_throw(func.insn_of(2usize));
+            });
+        }, |_| {
+            func.insn_store(result, func.insn_add(result, func.insn_of(10i32)));
+        });
+        func.insn_return(result);
+    }, {
+        assert_eq!(func(1), 1);
+        assert_eq!(func(-1), 11);
+    });
+}
+
+#[test]
+fn test_finally() {
+    let mut ctx = Context::<()>::new();
+    let mut cleanups = 0u32;
+    let mut cleanup = |n: u32| cleanups += n;
+    jit_func!(&mut ctx, func, fn(n: u32) -> u32 {
+        func.build_try(|| {
+            func.build_try_finally(|| {
+                func.build_if(func.insn_eq(n, func.insn_of(0u32)), || {
+                    func.insn_return(n);
+                });
+                func.build_if(func.insn_eq(n, func.insn_of(1u32)), || {
+                    func.insn_throw(func.insn_of(1usize));
+                });
+            }, || {
+                func.insn_call_rust_mut(Some("cleanup"), &mut cleanup, &[n], flags::NO_THROW);
+            });
+        }, |_| {
+            func.insn_return(func.insn_of(100u32));
+        });
+        func.insn_return(n);
+    }, {
+        assert_eq!(func(0), 0);
+        assert_eq!(func(1), 100);
+        assert_eq!(func(2), 2);
+        assert_eq!(cleanups, 3);
+    });
+}
+
+#[test]
+fn test_finally_branch_out() {
+    let ctx = Context::<()>::new();
+    let cleanups = Cell::new(0u32);
+    let cleanup = |n: u32| cleanups.set(cleanups.get() + n);
+    let func = UncompiledFunction::new(&ctx, &get::<fn(u32) -> u32>());
+    {
+        let n = &func[0];
+        let mut skipped = Label::new(&func);
+        func.build_try_finally(|| {
+            // branching out of the body skips the finally block
+            func.insn_branch_if(func.insn_eq(n, func.insn_of(0u32)), &mut skipped);
+        }, || {
+            func.insn_call_rust(Some("cleanup"), &cleanup, &[n], flags::NO_THROW);
+        });
+        func.insn_return(n);
+        func.insn_label(&mut skipped);
+        func.insn_return(func.insn_of(10u32));
+    }
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(u32,), u32>((0,)), Ok(10));
+    assert_eq!(cleanups.get(), 0);
+    assert_eq!(func.try_call::<(u32,), u32>((3,)), Ok(3));
+    assert_eq!(cleanups.get(), 3);
+}
+
+#[test]
+fn test_filter() {
+    let mut ctx = Context::<()>::new();
+    jit_func!(&mut ctx, func, fn(n: usize) -> usize {
+        func.build_try(|| {
+            func.build_try_filter(|| {
+                func.insn_throw(n);
+            }, |exc| {
+                func.insn_eq(exc, func.insn_of(1usize))
+            }, |_| {
+                func.insn_return(func.insn_of(10usize));
+            });
+        }, |_| {
+            func.insn_return(func.insn_of(20usize));
+        });
+        func.insn_default_return();
+    }, {
+        assert_eq!(func(1), 10);
+        assert_eq!(func(2), 20);
+    });
+}
+
+#[test]
+fn test_try_call() {
+    let mut ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    {
+        let x = &func[0];
+        func.build_if(func.insn_eq(x, func.insn_of(1i32)), || {
+            func.insn_throw(func.insn_of(42usize));
+        });
+        func.insn_check_null(x);
+        func.insn_return(func.insn_add_ovf(x, func.insn_of(i32::max_value() - 5)));
+    }
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(i32,), i32>((2,)), Ok(i32::max_value() - 3));
+    assert_eq!(func.try_call::<(i32,), i32>((1,)), Err(JitException::Object(42 as *mut _)));
+    assert_eq!(func.try_call::<(i32,), i32>((0,)), Err(JitException::Builtin(BuiltinException::NullReference)));
+    assert_eq!(func.try_call::<(i32,), i32>((10,)), Err(JitException::Builtin(BuiltinException::Overflow)));
+}
+
+#[test]
+fn test_try_call_args() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(u8, i64, u8) -> i64>());
+    let sum = func.insn_add(&func[0], &func[2]);
+    func.insn_return(func.insn_sub(&func[1], func.insn_convert(sum, &get::<i64>(), false)));
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(u8, i64, u8), i64>((1, 100, 2)), Ok(97));
+}
+
+#[test]
+fn test_try_call_other_thread() {
+    let ctx = Context::<()>::new();
+    // the context is made on this thread, so the other thread has no handler
+    let ctx_ref: &'static Context = unsafe { mem::transmute(&*ctx) };
+    let caught = thread::spawn(move || {
+        let func = UncompiledFunction::new(ctx_ref, &get::<fn(i32, i32) -> i32>());
+        func.insn_return(func.insn_div(&func[0], &func[1]));
+        let func = UncompiledFunction::compile(func);
+        func.try_call::<(i32, i32), i32>((1, 0)) == Err(JitException::Builtin(BuiltinException::DivisionByZero))
+    }).join().unwrap();
+    assert!(caught);
+}
+
+#[test]
+#[should_panic]
+fn test_try_call_wrong_signature() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    func.insn_return(&func[0]);
+    let func = UncompiledFunction::compile(func);
+    let _ = func.try_call::<(i32,), f64>((1,));
+}
+
+#[test]
+fn test_stack_trace() {
+    let ctx = Context::<()>::new();
+    let trace_len = Cell::new(0);
+    let check_trace = |trace: *mut ()| {
+        let trace = unsafe { StackTrace::from_raw(&ctx, trace as *mut _) };
+        trace_len.set(trace.len());
+        let frame = trace.frames().find(|frame| frame.function.is_some()).unwrap();
+        assert_eq!(frame.function.unwrap().get_name(), Some("thrower"));
+        assert_eq!(frame.offset, Some(12));
+    };
+    let func = UncompiledFunction::new(&ctx, &get::<fn()>());
+    func.set_name("thrower");
+    func.insn_mark_offset(12);
+    let trace = func.insn_get_call_stack();
+    func.insn_call_rust(Some("check_trace"), &check_trace, &[trace], flags::NO_THROW);
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(), ()>(()), Ok(()));
+    assert!(trace_len.get() > 0);
+}
+
+#[test]
+fn test_location() {
+    let ctx = Context::<()>::new();
+    let location = Cell::new(None);
+    let find_location = |trace: *mut ()| {
+        let trace = unsafe { StackTrace::from_raw(&ctx, trace as *mut _) };
+        let frame = trace.frames().find(|frame| frame.function.is_some()).unwrap();
+        location.set(frame.location.map(|loc| (loc.line, loc.column)));
+        assert_eq!(frame.location.map(|loc| &*loc.file), Some("script.foo"));
+        assert_eq!(ctx.location_at(frame.pc), frame.location);
+    };
+    let func = UncompiledFunction::new(&ctx, &get::<fn()>());
+    func.insn_mark_location("script.foo", 11, 1);
+    func.insn_mark_location("script.foo", 12, 4);
+    let trace = func.insn_get_call_stack();
+    func.insn_call_rust(Some("find_location"), &find_location, &[trace], flags::NO_THROW);
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(), ()>(()), Ok(()));
+    assert_eq!(location.get(), Some((12, 4)));
+}
+
+#[test]
+fn test_location_after_offset() {
+    let ctx = Context::<()>::new();
+    let marks = RefCell::new(Vec::new());
+    let find_marks = |trace: *mut ()| {
+        let trace = unsafe { StackTrace::from_raw(&ctx, trace as *mut _) };
+        let frame = trace.frames().find(|frame| frame.function.is_some()).unwrap();
+        marks.borrow_mut().push((frame.offset, frame.location.map(|loc| loc.line)));
+    };
+    let func = UncompiledFunction::new(&ctx, &get::<fn()>());
+    func.insn_mark_location("script.foo", 3, 1);
+    func.insn_mark_offset(0);
+    let trace = func.insn_get_call_stack();
+    func.insn_call_rust(Some("find_marks"), &find_marks, &[trace], flags::NO_THROW);
+    func.insn_mark_location("script.foo", 4, 1);
+    let trace = func.insn_get_call_stack();
+    func.insn_call_rust(Some("find_marks"), &find_marks, &[trace], flags::NO_THROW);
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(func.try_call::<(), ()>(()), Ok(()));
+    assert_eq!(*marks.borrow(), [(Some(0), None), (None, Some(4))]);
+}
+
+#[test]
+#[should_panic]
+fn test_negative_offset() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn()>());
+    func.insn_mark_offset(-2);
+}
@@ -0,0 +1,37 @@
+extern crate jit;
+use jit::*;
+
+extern "C" fn triple(x: i32) -> i32 {
+    x * 3
+}
+
+unsafe extern "C" fn read(ptr: *const i32) -> i32 {
+    *ptr
+}
+
+#[test]
+fn test_extern_fn_types() {
+    let sig = get::<extern "C" fn(i32) -> i32>();
+    assert!(sig.is_signature());
+    assert_eq!(sig.params().count(), 1);
+    assert_eq!(sig.get_return(), Some(&*get::<i32>()));
+    let sig = get::<unsafe extern "C" fn(*const i32, f64) -> f64>();
+    assert!(sig.is_signature());
+    assert_eq!(sig.params().count(), 2);
+}
+
+#[test]
+fn test_extern_fn_constants() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*const i32) -> i32>());
+    {
+        let triple_f = func.insn_of(triple as extern "C" fn(i32) -> i32);
+        let read_f = func.insn_of(read as unsafe extern "C" fn(*const i32) -> i32);
+        let value = func.insn_call_indirect(read_f, &get::<unsafe extern "C" fn(*const i32) -> i32>(), &[&func[0]], flags::CallFlags::empty());
+        let tripled = func.insn_call_indirect(triple_f, &get::<extern "C" fn(i32) -> i32>(), &[value], flags::CallFlags::empty());
+        func.insn_return(tripled);
+    }
+    let func = UncompiledFunction::compile(func);
+    let x = 14;
+    assert_eq!(func.try_call::<(*const i32,), i32>((&x,)), Ok(42));
+}
@@ -0,0 +1,105 @@
+extern crate jit;
+#[macro_use]
+extern crate jit_derive;
+use jit::*;
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Compile, FromJit, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct Particle {
+    alive: bool,
+    position: [f32; 3],
+    id: u64
+}
+
+#[derive(Compile, FromJit, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+struct Wrapper<T> {
+    value: T
+}
+
+#[test]
+fn test_from_jit_primitives() {
+    let ty = get::<i32>();
+    let bytes = [0x78, 0x56, 0x34, 0x12];
+    assert_eq!(i32::from_bytes(&ty, &bytes), Ok(0x12345678));
+    assert_eq!(u32::from_bytes(&ty, &bytes), Err(FromJitError::Type(ty.clone().into_owned())));
+    assert_eq!(i32::from_bytes(&ty, &bytes[..2]), Err(FromJitError::Size { expected: 4, got: 2 }));
+}
+
+#[test]
+fn test_from_jit_tuples_and_arrays() {
+    let ty = get::<(u8, f64, i16)>();
+    let (a, b, c) = (ty.fields().nth(0).unwrap(), ty.fields().nth(1).unwrap(), ty.fields().nth(2).unwrap());
+    let mut bytes = vec![0; ty.get_size()];
+    bytes[a.get_offset()] = 9;
+    bytes[b.get_offset() + 7] = 0x40;
+    bytes[c.get_offset()] = 0xff;
+    bytes[c.get_offset() + 1] = 0xff;
+    assert_eq!(<(u8, f64, i16)>::from_bytes(&ty, &bytes), Ok((9, 2.0, -1)));
+    assert!(<(u8, f64)>::from_bytes(&ty, &bytes).is_err());
+    let ty = get::<[u16; 3]>();
+    assert_eq!(<[u16; 3]>::from_bytes(&ty, &[1, 0, 2, 0, 3, 0]), Ok([1, 2, 3]));
+    assert!(<[u16; 2]>::from_bytes(&ty, &[1, 0, 2, 0, 3, 0]).is_err());
+}
+
+#[test]
+fn test_from_jit_out_parameter() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(*mut Particle)>());
+    let particle = Particle { alive: true, position: [1.0, -2.0, 0.5], id: 42 };
+    func.insn_store_relative(&func[0], 0, particle.compile(&func));
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    let ty = get::<Particle>();
+    let mut bytes = vec![0u8; ty.get_size()];
+    assert_eq!(func.try_call::<(*mut Particle,), ()>((bytes.as_mut_ptr() as *mut Particle,)), Ok(()));
+    assert_eq!(Particle::from_bytes(&ty, &bytes), Ok(particle));
+    assert!(Wrapper::<u64>::from_bytes(&ty, &bytes).is_err());
+}
+
+#[test]
+fn test_from_jit_apply_result() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> Wrapper<i32>>());
+    let result = Val::new(&func, &get::<Wrapper<i32>>());
+    result.set_addressable();
+    func.insn_store_relative(func.insn_address_of(result), 0, func.insn_mul(&func[0], func.insn_of(3i32)));
+    func.insn_return(result);
+    let func = UncompiledFunction::compile(func);
+    let ty = get::<Wrapper<i32>>();
+    let value = func.apply_dynamic(&[JitValue::I32(5)]).unwrap();
+    assert_eq!(value.decode::<Wrapper<i32>>(&ty), Ok(Wrapper { value: 15 }));
+    assert!(value.decode::<Wrapper<u8>>(&ty).is_err());
+}
+
+static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+/// A byte that can't be zero, which counts how many times it is dropped
+struct NonZero(u8);
+impl Drop for NonZero {
+    fn drop(&mut self) {
+        DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+impl FromJit for NonZero {
+    fn is_type(ty: &Ty) -> bool {
+        u8::is_type(ty)
+    }
+    unsafe fn read_unchecked(ty: &Ty, ptr: *const c_void) -> NonZero {
+        match u8::read_unchecked(ty, ptr) {
+            0 => panic!("read a zero"),
+            byte => NonZero(byte)
+        }
+    }
+}
+
+#[test]
+fn test_from_jit_array_panic() {
+    let ty = get::<[u8; 3]>();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| <[NonZero; 3]>::from_bytes(&ty, &[1, 0, 2])));
+    assert!(result.is_err());
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,51 @@
+extern crate jit;
+use jit::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+struct CountingManager {
+    inner: VmemMemoryManager,
+    code: Rc<Cell<usize>>
+}
+impl MemoryManager for CountingManager {
+    fn alloc(&mut self, size: usize, kind: RegionKind) -> *mut u8 {
+        if kind == RegionKind::Code {
+            self.code.set(self.code.get() + size);
+        }
+        self.inner.alloc(size, kind)
+    }
+    fn free(&mut self, ptr: *mut u8, size: usize, kind: RegionKind) {
+        if kind == RegionKind::Code {
+            self.code.set(self.code.get() - size);
+        }
+        self.inner.free(ptr, size, kind)
+    }
+}
+
+#[test]
+fn test_memory_manager() {
+    let code = Rc::new(Cell::new(0));
+    {
+        let ctx = Context::<()>::new();
+        ctx.set_memory_manager(CountingManager {
+            inner: VmemMemoryManager::new(),
+            code: code.clone()
+        });
+        let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+        func.insn_return(func.insn_mul(&func[0], func.insn_of(3i32)));
+        let func = CompiledFunction::to_closure::<i32, i32>(UncompiledFunction::compile(func));
+        assert_eq!(func(5), 15);
+        assert!(code.get() > 0);
+    }
+    assert_eq!(code.get(), 0);
+}
+
+#[test]
+#[should_panic]
+fn test_late_memory_manager() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn() -> i32>());
+    func.insn_return(func.insn_of(1i32));
+    UncompiledFunction::compile(func);
+    ctx.set_memory_manager(VmemMemoryManager::new());
+}
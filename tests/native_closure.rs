@@ -0,0 +1,24 @@
+extern crate jit;
+use jit::*;
+
+#[test]
+fn test_native_closure() {
+    let ctx = Context::<()>::new();
+    let sig = get::<fn(f64, i32) -> f64>();
+    let offset = 0.5;
+    let closure = NativeClosure::new(&ctx, &sig, move |args, ret| {
+        assert_eq!(args.len(), 2);
+        let x = args.next::<f64>();
+        let n = args.next::<i32>();
+        ret.set(x * n as f64 + offset)
+    });
+    let func = UncompiledFunction::new(&ctx, &sig);
+    {
+        let result = unsafe {
+            func.insn_call_native(Some("scale"), closure.as_ptr() as *mut (), &sig, &[&func[0], &func[1]], flags::CallFlags::empty())
+        };
+        func.insn_return(result);
+    }
+    let func = CompiledFunction::to_closure::<(f64, i32), f64>(UncompiledFunction::compile(func));
+    assert_eq!(func(2.0, 3), 6.5);
+}
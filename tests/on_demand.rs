@@ -0,0 +1,90 @@
+extern crate jit;
+use jit::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+#[test]
+fn test_on_demand() {
+    let ctx = Context::<()>::new();
+    let builds = Rc::new(Cell::new(0));
+    let square_builds = builds.clone();
+    let square = UncompiledFunction::new_on_demand(&ctx, &get::<fn(i32) -> i32>(), move |func| {
+        square_builds.set(square_builds.get() + 1);
+        func.insn_return(func.insn_mul(&func[0], &func[0]));
+    });
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    {
+        let squared = func.insn_call(Some("square"), &square, None, &[&func[0]], flags::CallFlags::empty());
+        func.insn_return(func.insn_add(squared, func.insn_of(1i32)));
+    }
+    let func = UncompiledFunction::compile(func);
+    assert_eq!(builds.get(), 0);
+    assert!(!square.is_compiled());
+    let func = CompiledFunction::to_closure::<i32, i32>(func);
+    assert_eq!(func(3), 10);
+    assert_eq!(func(4), 17);
+    assert_eq!(builds.get(), 1);
+    assert!(square.is_compiled());
+}
+
+#[test]
+fn test_on_demand_entry_point() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new_on_demand(&ctx, &get::<fn(i32, i32) -> i32>(), |func| {
+        func.insn_return(func.insn_sub(&func[0], &func[1]));
+    });
+    assert!(!func.is_compiled());
+    assert_eq!(func.try_call::<(i32, i32), i32>((9, 4)), Ok(5));
+    assert!(func.is_compiled());
+    let sub = CompiledFunction::to_closure::<(i32, i32), i32>(func);
+    assert_eq!(sub(3, 5), -2);
+}
+
+#[test]
+fn test_on_demand_closure_before_build() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new_on_demand(&ctx, &get::<fn(i32) -> i32>(), |func| {
+        func.insn_return(func.insn_neg(&func[0]));
+    });
+    let neg = CompiledFunction::to_closure::<i32, i32>(func);
+    assert_eq!(neg(6), -6);
+}
+
+#[test]
+fn test_on_demand_failed_build() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new_on_demand(&ctx, &get::<fn(i32) -> i32>(), |_| {
+        panic!("cannot build this function")
+    });
+    assert_eq!(func.try_call::<(i32,), i32>((1,)), Err(JitException::Builtin(BuiltinException::CompileError)));
+    assert!(!func.is_compiled());
+}
+
+#[test]
+fn test_on_demand_driver() {
+    let ctx = Context::<()>::new();
+    let entered = Rc::new(Cell::new(0));
+    let driver_entered = entered.clone();
+    ctx.set_on_demand_driver(move |func, compile| {
+        assert!(!func.is_compiled());
+        driver_entered.set(driver_entered.get() + 1);
+        compile()
+    });
+    let func = UncompiledFunction::new_on_demand(&ctx, &get::<fn(i32) -> i32>(), |func| {
+        func.insn_return(func.insn_add(&func[0], func.insn_of(2i32)));
+    });
+    assert_eq!(func.try_call::<(i32,), i32>((3,)), Ok(5));
+    assert_eq!(func.try_call::<(i32,), i32>((4,)), Ok(6));
+    assert_eq!(entered.get(), 1);
+}
+
+#[test]
+fn test_on_demand_driver_error() {
+    let ctx = Context::<()>::new();
+    ctx.set_on_demand_driver(|_, _| Err(BuiltinException::OutOfMemory));
+    let func = UncompiledFunction::new_on_demand(&ctx, &get::<fn(i32) -> i32>(), |func| {
+        func.insn_return(&func[0]);
+    });
+    assert_eq!(func.try_call::<(i32,), i32>((1,)), Err(JitException::Builtin(BuiltinException::OutOfMemory)));
+    assert!(!func.is_compiled());
+}
@@ -0,0 +1,65 @@
+extern crate jit;
+use jit::*;
+
+#[test]
+fn test_rebuild() {
+    let ctx = Context::<()>::new();
+    let callee = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    callee.set_recompilable();
+    callee.insn_return(func_add(&callee, 1));
+    let callee = UncompiledFunction::compile(callee);
+    let caller = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    {
+        let result = caller.insn_call(Some("callee"), &callee, None, &[&caller[0]], flags::CallFlags::empty());
+        caller.insn_return(result);
+    }
+    let caller = CompiledFunction::to_closure::<i32, i32>(UncompiledFunction::compile(caller));
+    assert_eq!(caller(1), 2);
+    assert_eq!(callee.rebuild_as(&get::<fn(i32) -> i32>(), |func| func.insn_return(func_add(func, 10))), Ok(()));
+    assert_eq!(caller(1), 11);
+}
+
+fn func_add(func: &UncompiledFunction, n: i32) -> &Val {
+    func.insn_add(&func[0], func.insn_of(n))
+}
+
+#[test]
+#[should_panic]
+fn test_rebuild_signature() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    func.set_recompilable();
+    func.insn_return(&func[0]);
+    let func = UncompiledFunction::compile(func);
+    let _ = func.rebuild_as(&get::<fn(f64) -> f64>(), |func| func.insn_return(&func[0]));
+}
+
+#[test]
+#[should_panic]
+fn test_rebuild_params() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    func.set_recompilable();
+    func.insn_return(&func[0]);
+    let func = UncompiledFunction::compile(func);
+    let _ = func.rebuild_as(&get::<fn(i32, i32) -> i32>(), |func| func.insn_return(&func[0]));
+}
+
+#[test]
+#[should_panic]
+fn test_rebuild_not_recompilable() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    func.insn_return(&func[0]);
+    let func = UncompiledFunction::compile(func);
+    let _ = func.rebuild(|func| func.insn_return(&func[0]));
+}
+
+#[test]
+#[should_panic]
+fn test_rebuild_uncompiled() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    func.set_recompilable();
+    let _ = func.rebuild(|func| func.insn_return(&func[0]));
+}
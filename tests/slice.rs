@@ -0,0 +1,43 @@
+extern crate jit;
+use jit::*;
+
+#[test]
+fn test_slice_sum() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(&[f64]) -> f64>());
+    {
+        let slice = &func[0];
+        let sum = Val::new(&func, &get::<f64>());
+        let i = Val::new(&func, &get::<usize>());
+        func.insn_store(sum, func.insn_of(0.0f64));
+        func.insn_store(i, func.insn_of(0usize));
+        let mut start = Label::new(&func);
+        let mut end = Label::new(&func);
+        func.insn_label(&mut start);
+        func.insn_branch_if_not(func.insn_lt(i, slice.len()), &mut end);
+        func.insn_store(sum, func.insn_add(sum, slice.index_load(i)));
+        func.insn_store(i, func.insn_add(i, func.insn_of(1usize)));
+        func.insn_branch(&mut start);
+        func.insn_label(&mut end);
+        func.insn_return(sum);
+    }
+    let func = UncompiledFunction::compile(func);
+    let values: &[f64] = &[1.0, 2.5, 4.0];
+    assert_eq!(func.try_call::<(&[f64],), f64>((values,)), Ok(7.5));
+}
+
+#[test]
+fn test_slice_store() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(&mut [i32], i32)>());
+    func[0].index_store(&func[1], func.insn_of(9i32));
+    func.insn_default_return();
+    let func = UncompiledFunction::compile(func);
+    let mut values = [1, 2, 3];
+    assert_eq!(func.try_call::<(&mut [i32], i32), ()>((&mut values, 2)), Ok(()));
+    assert_eq!(values, [1, 2, 9]);
+    assert_eq!(func.try_call::<(&mut [i32], i32), ()>((&mut values, 3)),
+               Err(JitException::Builtin(BuiltinException::OutOfBounds)));
+    assert_eq!(func.try_call::<(&mut [i32], i32), ()>((&mut values, -1)),
+               Err(JitException::Builtin(BuiltinException::OutOfBounds)));
+}
@@ -21,4 +21,22 @@ impl Drop for PanicDrop {
 fn test_context_panic_tags() {
     let mut ctx = Context::<PanicDrop>::new();
     ctx[0] = PanicDrop(7);
-}
\ No newline at end of file
+}
+#[test]
+#[should_panic]
+fn test_context_reserved_tags() {
+    let ctx = Context::<usize>::new();
+    ctx[-1];
+}
+#[test]
+#[should_panic]
+fn test_context_option_tags() {
+    let ctx = Context::<usize>::new();
+    ctx[10000];
+}
+#[test]
+#[should_panic]
+fn test_context_reserved_tags_mut() {
+    let mut ctx = Context::<usize>::new();
+    ctx[-2] = 1;
+}
@@ -0,0 +1,63 @@
+extern crate jit;
+use jit::*;
+use std::cell::Cell;
+use std::rc::Rc;
+
+#[test]
+fn test_tiered() {
+    let ctx = Context::<()>::new();
+    let builds = Rc::new(Cell::new(0));
+    let tiered_builds = builds.clone();
+    // the first build adds 1 and the optimized one adds 100, so it's clear which runs
+    let func = UncompiledFunction::new_tiered(&ctx, &get::<fn(i32) -> i32>(), 3, move |func| {
+        let add = if tiered_builds.get() == 0 { 1i32 } else { 100 };
+        tiered_builds.set(tiered_builds.get() + 1);
+        func.insn_return(func.insn_add(&func[0], func.insn_of(add)));
+    });
+    let func = CompiledFunction::to_closure::<i32, i32>(func);
+    assert_eq!(builds.get(), 1);
+    for i in 0..3 {
+        assert_eq!(func(i), i + 1);
+    }
+    assert_eq!(builds.get(), 2);
+    for i in 3..6 {
+        assert_eq!(func(i), i + 100);
+    }
+    assert_eq!(builds.get(), 2);
+}
+
+#[test]
+fn test_tiered_threshold_zero() {
+    let ctx = Context::<()>::new();
+    let builds = Rc::new(Cell::new(0));
+    let tiered_builds = builds.clone();
+    let func = UncompiledFunction::new_tiered(&ctx, &get::<fn(i32) -> i32>(), 0, move |func| {
+        tiered_builds.set(tiered_builds.get() + 1);
+        func.insn_return(func.insn_add(&func[0], func.insn_of(1i32)));
+    });
+    let func = CompiledFunction::to_closure::<i32, i32>(func);
+    assert_eq!(builds.get(), 1);
+    for i in 0..3 {
+        assert_eq!(func(i), i + 1);
+    }
+    assert_eq!(builds.get(), 1);
+}
+
+#[test]
+fn test_tiered_failed_promotion() {
+    let ctx = Context::<()>::new();
+    let builds = Rc::new(Cell::new(0));
+    let tiered_builds = builds.clone();
+    let func = UncompiledFunction::new_tiered(&ctx, &get::<fn(i32) -> i32>(), 2, move |func| {
+        tiered_builds.set(tiered_builds.get() + 1);
+        if tiered_builds.get() > 1 {
+            panic!("cannot optimize this function")
+        }
+        func.insn_return(func.insn_add(&func[0], func.insn_of(1i32)));
+    });
+    let func = CompiledFunction::to_closure::<i32, i32>(func);
+    for i in 0..5 {
+        assert_eq!(func(i), i + 1);
+    }
+    assert_eq!(builds.get(), 2);
+}
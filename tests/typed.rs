@@ -0,0 +1,49 @@
+extern crate jit;
+use jit::*;
+use std::panic::{self, AssertUnwindSafe};
+
+#[test]
+fn test_typed_function() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(f64, f64, f64) -> f64>());
+    func.insn_return(func.insn_add(func.insn_mul(&func[0], &func[1]), &func[2]));
+    let func: TypedFunction<(f64, f64, f64), f64> = TypedFunction::new(UncompiledFunction::compile(func));
+    assert_eq!(func(2.0, 3.0, 1.0), 7.0);
+    let copy = func;
+    assert_eq!(copy(1.0, 1.0, 1.0), 2.0);
+    assert!(func.get_function().is_compiled());
+}
+
+#[test]
+fn test_typed_function_no_args() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn() -> i32>());
+    func.insn_return(func.insn_of(42i32));
+    let func: TypedFunction<(), i32> = TypedFunction::new(UncompiledFunction::compile(func));
+    assert_eq!(func(), 42);
+}
+
+#[test]
+#[should_panic]
+fn test_typed_function_bad_signature() {
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32) -> i32>());
+    func.insn_return(&func[0]);
+    let _: TypedFunction<(f64,), f64> = TypedFunction::new(UncompiledFunction::compile(func));
+}
+
+#[test]
+fn test_typed_function_bad_signature_checked_always() {
+    // this runs without `should_panic` so it checks the message, which comes
+    // from `assert_sig` rather than a debug assertion, under `--release` too
+    let ctx = Context::<()>::new();
+    let func = UncompiledFunction::new(&ctx, &get::<fn(i32, i32) -> i32>());
+    func.insn_return(&func[0]);
+    let func = UncompiledFunction::compile(func);
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let _: TypedFunction<(i32,), i32> = TypedFunction::new(func);
+    }));
+    let message = result.unwrap_err();
+    let message = message.downcast_ref::<String>().unwrap();
+    assert!(message.contains("can't be called with arguments"), "{}", message);
+}
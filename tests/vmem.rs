@@ -0,0 +1,34 @@
+extern crate jit;
+use jit::*;
+
+#[test]
+fn test_virtual_region() {
+    let page = page_size();
+    assert_eq!(round_up_to_page(1), page);
+    assert_eq!(round_down_to_page(page + 1), page);
+    let region = VirtualRegion::reserve(page * 2 + 1).unwrap();
+    assert_eq!(region.len(), page * 3);
+    assert!(region.commit(page, page, Protection::ReadWrite));
+    unsafe {
+        let ptr = region.as_ptr().offset(page as isize);
+        *ptr = 7;
+        assert_eq!(*ptr, 7);
+    }
+    assert!(region.protect(page, page, Protection::Read));
+    assert!(region.decommit(page, page));
+}
+
+#[test]
+#[should_panic]
+fn test_virtual_region_bounds() {
+    let region = VirtualRegion::reserve_committed(page_size(), Protection::ReadWrite).unwrap();
+    region.commit(0, region.len() + 1, Protection::ReadWrite);
+}
+
+#[test]
+fn test_virtual_region_too_big() {
+    let size = u32::max_value() as usize + 1;
+    assert!(VirtualRegion::reserve(size).is_none());
+    assert!(VirtualRegion::reserve_committed(size, Protection::ReadWrite).is_none());
+    assert!(VirtualRegion::reserve(usize::max_value()).is_none());
+}